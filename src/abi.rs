@@ -0,0 +1,60 @@
+// Where arguments and return values go under the System V AMD64 calling convention.
+// Crescent has no floating point, so every eightbyte of a small struct is INTEGER class
+use crate::symbols::{ResolvedType, Symbols};
+
+pub const MAX_REGISTER_ARGS: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArgClass {
+    Regs(usize), // Fits in this many general purpose registers
+    Memory,      // Passed on the stack, or returned through a pointer the caller hands over
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArgLoc {
+    Regs(usize),  // Index of the first parameter register it occupies
+    Stack(usize), // Offset from the first stack argument
+}
+
+// Anything larger than two eightbytes goes through memory
+pub fn classify(symbols: &Symbols, ty: &ResolvedType) -> ArgClass {
+    let size = symbols.size_of(ty);
+    if symbols.is_aggregate(ty) && size > 16 {
+        ArgClass::Memory
+    } else {
+        ArgClass::Regs(size.div_ceil(8).max(1))
+    }
+}
+
+// A struct returned in memory is written through a pointer passed as a hidden first argument
+pub fn returns_in_memory(symbols: &Symbols, return_ty: &ResolvedType) -> bool {
+    classify(symbols, return_ty) == ArgClass::Memory
+}
+
+// Location of every parameter, along with how many bytes of stack arguments there are.
+// A struct that doesn't fit in the remaining registers goes on the stack whole, while later
+// arguments can still take the registers it left unused
+pub fn arg_locations(
+    symbols: &Symbols,
+    param_tys: &[ResolvedType],
+    return_ty: &ResolvedType,
+) -> (Vec<ArgLoc>, usize) {
+    let mut next_reg = returns_in_memory(symbols, return_ty) as usize;
+    let mut stack_size = 0;
+    let mut locs = vec![];
+
+    for ty in param_tys {
+        match classify(symbols, ty) {
+            ArgClass::Regs(count) if next_reg + count <= MAX_REGISTER_ARGS => {
+                locs.push(ArgLoc::Regs(next_reg));
+                next_reg += count;
+            }
+            _ => {
+                locs.push(ArgLoc::Stack(stack_size));
+                stack_size += symbols.size_of(ty).next_multiple_of(8);
+            }
+        }
+    }
+
+    (locs, stack_size)
+}
@@ -1,7 +1,7 @@
-use crate::parser::{ParsedParam, ParsedType};
+use crate::parser::{ParsedField, ParsedParam, ParsedType};
 use crate::semantic::{IfID, LoopID};
-use crate::symbols::SymbolID;
-use crate::tokens::Token;
+use crate::symbols::{ResolvedType, SymbolID};
+use crate::tokens::{SourceSpan, Token};
 
 #[derive(Debug, Clone, Copy)]
 pub enum BinOpKind {
@@ -10,18 +10,29 @@ pub enum BinOpKind {
     Sub,
     Mult,
     Div,
+    Mod,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
     Equals,
     NotEquals,
     LessThan,
     LessEq,
     GreaterThan,
     GreaterEq,
+    And,
+    Or,
 }
 
 #[derive(Debug, Clone, Copy)]
 pub enum UnOpKind {
     Not,
     Neg,
+    BitNot,
+    AddrOf, // &x
+    Deref,  // *p
 }
 
 #[derive(Debug)]
@@ -31,6 +42,12 @@ pub struct BinOpInfo {
     pub rhs: Box<Expr>,
 }
 
+#[derive(Debug)]
+pub struct LiteralInfo {
+    pub value: i128, // Wide enough for every i64 and u64, anything larger is out of range anyway
+    pub suffix: Option<String>, // Type written right after the digits, as in `10u8`
+}
+
 #[derive(Debug)]
 pub struct UnOpInfo {
     pub op: UnOpKind,
@@ -41,6 +58,64 @@ pub struct UnOpInfo {
 pub struct FuncCallInfo {
     pub id: Option<SymbolID>,
     pub args: Vec<Box<Expr>>,
+    pub slot: Option<i64>, // Where a returned struct is kept, since structs are held by address
+}
+
+// `lhs op= rhs`, where op is the arithmetic operator applied before storing
+#[derive(Debug)]
+pub struct CompoundAssignInfo {
+    pub op: BinOpKind,
+    pub lhs: Box<Expr>,
+    pub rhs: Box<Expr>,
+}
+
+#[derive(Debug)]
+pub struct IndexInfo {
+    pub base: Box<Expr>,
+    pub index: Box<Expr>,
+}
+
+// Array literals are built in a stack slot reserved by semantic analysis
+#[derive(Debug)]
+pub struct ArrayInfo {
+    pub elems: Vec<Box<Expr>>,
+    pub slot: Option<i64>,
+}
+
+// `[value; count]`, the value is evaluated once and copied into every element
+#[derive(Debug)]
+pub struct ArrayRepeatInfo {
+    pub value: Box<Expr>,
+    pub count: usize,
+    pub slot: Option<i64>,
+}
+
+// `base.field`, the field's name is the expression's token
+#[derive(Debug)]
+pub struct FieldInfo {
+    pub base: Box<Expr>,
+    pub offset: Option<usize>, // Resolved by semantic analysis
+}
+
+#[derive(Debug)]
+pub struct FieldInit {
+    pub token: Token,
+    pub expr: Box<Expr>,
+    pub offset: Option<usize>,
+}
+
+// `Name { field: value, ... }`, the struct's name is the expression's token. Built in a
+// stack slot just like array literals
+#[derive(Debug)]
+pub struct StructLitInfo {
+    pub fields: Vec<FieldInit>,
+    pub slot: Option<i64>,
+}
+
+#[derive(Debug)]
+pub struct CastInfo {
+    pub expr: Box<Expr>,
+    pub ty: ParsedType,
 }
 
 // Different kinds of expressions recognized in the language
@@ -50,42 +125,88 @@ pub enum ExprKind {
     UnOp(UnOpInfo),
     Var(Option<SymbolID>),
     Func(FuncCallInfo),
-    Literal(i64),
+    Literal(LiteralInfo),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    Cast(CastInfo),
+    CompoundAssign(CompoundAssignInfo),
+    Index(IndexInfo),
+    Array(ArrayInfo),
+    ArrayRepeat(ArrayRepeatInfo),
+    Field(FieldInfo),
+    StructLit(StructLitInfo),
 }
 
 #[derive(Debug)]
 pub struct Expr {
     pub kind: ExprKind,
     pub token: Token,
+    pub span: SourceSpan,
+    pub ty: Option<ResolvedType>, // Filled in by semantic analysis
 }
 
 impl Expr {
     pub fn var(token: Token) -> Self {
         Expr {
             kind: ExprKind::Var(None),
+            span: token.span,
             token,
+            ty: None,
         }
     }
 
-    pub fn func(args: Vec<Expr>, token: Token) -> Self {
+    pub fn func(args: Vec<Expr>, token: Token, span: SourceSpan) -> Self {
         Expr {
             kind: ExprKind::Func(FuncCallInfo {
                 id: None,
                 args: args.into_iter().map(Box::new).collect(),
+                slot: None,
             }),
+            token,
+            span,
+            ty: None,
+        }
+    }
 
+    pub fn lit(info: LiteralInfo, token: Token) -> Self {
+        Expr {
+            kind: ExprKind::Literal(info),
+            span: token.span,
             token,
+            ty: None,
         }
     }
 
-    pub fn lit(val: i64, token: Token) -> Self {
+    pub fn char_lit(token: Token) -> Self {
         Expr {
-            kind: ExprKind::Literal(val),
+            kind: ExprKind::Char(token.lexeme.chars().next().unwrap_or('\0')),
+            span: token.span,
             token,
+            ty: None,
+        }
+    }
+
+    pub fn str_lit(token: Token) -> Self {
+        Expr {
+            kind: ExprKind::Str(token.lexeme.to_owned()),
+            span: token.span,
+            token,
+            ty: None,
+        }
+    }
+
+    pub fn bool_lit(val: bool, token: Token) -> Self {
+        Expr {
+            kind: ExprKind::Bool(val),
+            span: token.span,
+            token,
+            ty: None,
         }
     }
 
     pub fn binary_op(op: BinOpKind, lhs: Expr, rhs: Expr, token: Token) -> Self {
+        let span = lhs.span.to(rhs.span);
         Expr {
             kind: ExprKind::BinOp(BinOpInfo {
                 op,
@@ -93,16 +214,113 @@ impl Expr {
                 rhs: Box::new(rhs),
             }),
             token,
+            span,
+            ty: None,
+        }
+    }
+
+    pub fn index(base: Expr, index: Expr, token: Token, span: SourceSpan) -> Self {
+        Expr {
+            kind: ExprKind::Index(IndexInfo {
+                base: Box::new(base),
+                index: Box::new(index),
+            }),
+            token,
+            span,
+            ty: None,
+        }
+    }
+
+    pub fn array(elems: Vec<Expr>, token: Token, span: SourceSpan) -> Self {
+        Expr {
+            kind: ExprKind::Array(ArrayInfo {
+                elems: elems.into_iter().map(Box::new).collect(),
+                slot: None,
+            }),
+            token,
+            span,
+            ty: None,
+        }
+    }
+
+    pub fn array_repeat(value: Expr, count: usize, token: Token, span: SourceSpan) -> Self {
+        Expr {
+            kind: ExprKind::ArrayRepeat(ArrayRepeatInfo {
+                value: Box::new(value),
+                count,
+                slot: None,
+            }),
+            token,
+            span,
+            ty: None,
+        }
+    }
+
+    pub fn field(base: Expr, token: Token, span: SourceSpan) -> Self {
+        Expr {
+            kind: ExprKind::Field(FieldInfo {
+                base: Box::new(base),
+                offset: None,
+            }),
+            token,
+            span,
+            ty: None,
+        }
+    }
+
+    pub fn struct_lit(fields: Vec<(Token, Expr)>, token: Token, span: SourceSpan) -> Self {
+        let fields = fields
+            .into_iter()
+            .map(|(token, expr)| FieldInit {
+                token,
+                expr: Box::new(expr),
+                offset: None,
+            })
+            .collect();
+        Expr {
+            kind: ExprKind::StructLit(StructLitInfo { fields, slot: None }),
+            token,
+            span,
+            ty: None,
+        }
+    }
+
+    pub fn compound_assign(op: BinOpKind, lhs: Expr, rhs: Expr, token: Token) -> Self {
+        let span = lhs.span.to(rhs.span);
+        Expr {
+            kind: ExprKind::CompoundAssign(CompoundAssignInfo {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            }),
+            token,
+            span,
+            ty: None,
         }
     }
 
     pub fn unary_op(op: UnOpKind, expr: Expr, token: Token) -> Self {
+        let span = token.span.to(expr.span);
         Expr {
             kind: ExprKind::UnOp(UnOpInfo {
                 op,
                 expr: Box::new(expr),
             }),
             token,
+            span,
+            ty: None,
+        }
+    }
+
+    pub fn cast(expr: Expr, ty: ParsedType, token: Token, span: SourceSpan) -> Self {
+        Expr {
+            kind: ExprKind::Cast(CastInfo {
+                expr: Box::new(expr),
+                ty,
+            }),
+            token,
+            span,
+            ty: None,
         }
     }
 }
@@ -110,11 +328,17 @@ impl Expr {
 #[derive(Debug)]
 pub struct FuncDeclInfo {
     pub id: Option<SymbolID>,
-    pub ty: ParsedType,
+    pub ty: Option<ParsedType>, // None when no return type is written, meaning void
     pub params: Vec<ParsedParam>,
     pub body: Box<Stmt>,
 }
 
+#[derive(Debug)]
+pub struct StructDeclInfo {
+    pub id: Option<SymbolID>,
+    pub fields: Vec<ParsedField>,
+}
+
 #[derive(Debug)]
 pub struct VarDeclInfo {
     pub id: Option<SymbolID>,
@@ -140,14 +364,16 @@ pub struct WhileInfo {
 #[derive(Debug)]
 pub struct ReturnInfo {
     pub id: Option<SymbolID>,
-    pub expr: Box<Expr>,
+    pub expr: Option<Box<Expr>>,
 }
 
 // Different kinds of statements recognized in the language
 #[derive(Debug)]
 pub enum StmtKind {
     VarDecl(VarDeclInfo),
+    ConstDecl(VarDeclInfo),
     FuncDecl(FuncDeclInfo),
+    StructDecl(StructDeclInfo),
     If(IfInfo),
     While(WhileInfo),
     ExprStmt(Box<Expr>),
@@ -162,17 +388,19 @@ pub enum StmtKind {
 pub struct Stmt {
     pub kind: StmtKind,
     pub token: Token,
+    pub span: SourceSpan,
 }
 
 impl Stmt {
-    pub fn block(statements: Vec<Stmt>, token: Token) -> Self {
+    pub fn block(statements: Vec<Stmt>, token: Token, span: SourceSpan) -> Self {
         Stmt {
             kind: StmtKind::Block(statements),
             token,
+            span,
         }
     }
 
-    pub fn var_decl(ty: ParsedType, expr: Expr, token: Token) -> Self {
+    pub fn var_decl(ty: ParsedType, expr: Expr, token: Token, span: SourceSpan) -> Self {
         Stmt {
             kind: StmtKind::VarDecl(VarDeclInfo {
                 id: None,
@@ -180,10 +408,29 @@ impl Stmt {
                 expr: Box::new(expr),
             }),
             token,
+            span,
         }
     }
 
-    pub fn func_decl(ty: ParsedType, params: Vec<ParsedParam>, body: Stmt, token: Token) -> Stmt {
+    pub fn const_decl(ty: ParsedType, expr: Expr, token: Token, span: SourceSpan) -> Self {
+        Stmt {
+            kind: StmtKind::ConstDecl(VarDeclInfo {
+                id: None,
+                ty,
+                expr: Box::new(expr),
+            }),
+            token,
+            span,
+        }
+    }
+
+    pub fn func_decl(
+        ty: Option<ParsedType>,
+        params: Vec<ParsedParam>,
+        body: Stmt,
+        token: Token,
+        span: SourceSpan,
+    ) -> Stmt {
         Stmt {
             kind: StmtKind::FuncDecl(FuncDeclInfo {
                 id: None,
@@ -192,10 +439,25 @@ impl Stmt {
                 body: Box::new(body),
             }),
             token,
+            span,
         }
     }
 
-    pub fn if_else(cond: Expr, do_if: Stmt, do_else: Option<Stmt>, token: Token) -> Self {
+    pub fn struct_decl(fields: Vec<ParsedField>, token: Token, span: SourceSpan) -> Stmt {
+        Stmt {
+            kind: StmtKind::StructDecl(StructDeclInfo { id: None, fields }),
+            token,
+            span,
+        }
+    }
+
+    pub fn if_else(
+        cond: Expr,
+        do_if: Stmt,
+        do_else: Option<Stmt>,
+        token: Token,
+        span: SourceSpan,
+    ) -> Self {
         Stmt {
             kind: StmtKind::If(IfInfo {
                 id: None,
@@ -204,10 +466,11 @@ impl Stmt {
                 do_else: do_else.map(Box::new),
             }),
             token,
+            span,
         }
     }
 
-    pub fn while_loop(cond: Expr, body: Stmt, token: Token) -> Self {
+    pub fn while_loop(cond: Expr, body: Stmt, token: Token, span: SourceSpan) -> Self {
         Stmt {
             kind: StmtKind::While(WhileInfo {
                 id: None,
@@ -215,34 +478,38 @@ impl Stmt {
                 body: Box::new(body),
             }),
             token,
+            span,
         }
     }
 
-    pub fn return_stmt(expr: Expr, token: Token) -> Self {
+    pub fn return_stmt(expr: Option<Expr>, token: Token, span: SourceSpan) -> Self {
         Stmt {
             kind: StmtKind::Return(ReturnInfo {
                 id: None,
-                expr: Box::new(expr),
+                expr: expr.map(Box::new),
             }),
             token,
+            span,
         }
     }
 
-    pub fn trivial_stmt(kind: StmtKind, token: Token) -> Self {
-        Stmt { kind, token }
+    pub fn trivial_stmt(kind: StmtKind, token: Token, span: SourceSpan) -> Self {
+        Stmt { kind, token, span }
     }
 
-    pub fn continue_stmt(token: Token) -> Self {
+    pub fn continue_stmt(token: Token, span: SourceSpan) -> Self {
         Stmt {
             kind: StmtKind::Continue(None),
             token,
+            span,
         }
     }
 
-    pub fn break_stmt(token: Token) -> Self {
+    pub fn break_stmt(token: Token, span: SourceSpan) -> Self {
         Stmt {
             kind: StmtKind::Break(None),
             token,
+            span,
         }
     }
 }
@@ -250,9 +517,11 @@ impl Stmt {
 impl From<Expr> for Stmt {
     fn from(expr: Expr) -> Self {
         let token = expr.token.clone();
+        let span = expr.span;
         Stmt {
             kind: StmtKind::ExprStmt(Box::new(expr)),
             token,
+            span,
         }
     }
 }
@@ -11,20 +11,26 @@ use std::{
 };
 
 use crate::{
+    abi::{self, ArgLoc},
     ast::{
-        BinOpInfo, BinOpKind, Expr, ExprKind, FuncCallInfo, IfInfo, ReturnInfo, UnOpInfo, UnOpKind,
-        VarDeclInfo, WhileInfo,
+        ArrayInfo, ArrayRepeatInfo, BinOpInfo, BinOpKind, CastInfo, CompoundAssignInfo, Expr,
+        ExprKind, FieldInfo, FuncCallInfo, IfInfo, IndexInfo, ReturnInfo, StructLitInfo, UnOpInfo,
+        UnOpKind, VarDeclInfo, WhileInfo,
     },
     semantic::{IfID, LoopID},
-    symbols::{SymbolID, Symbols},
+    symbols::{ResolvedType, StaticData, Storage, SymbolID, Symbols},
+    tokens::SourceSpan,
 };
 
 use crate::{
     ast::{FuncDeclInfo, Program, Stmt, StmtKind},
-    compiler::Context,
+    compiler::{BuildMode, Context},
     diagnostic::{Diagnostic, DiagnosticKind},
 };
 
+// Exit code of a program stopped by a failed runtime check
+const TRAP_EXIT_CODE: i64 = 101;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum Register {
     Rax,
@@ -67,7 +73,7 @@ impl fmt::Display for Register {
 
 // TODO: Restructure registers to avoid needing to do this
 impl Register {
-    pub fn to_8bit(&self) -> &'static str {
+    pub fn to_8bit(self) -> &'static str {
         match self {
             Register::Rax => "%al",
             Register::Rbx => "%bl",
@@ -85,6 +91,44 @@ impl Register {
             Register::R15 => "%r15b",
         }
     }
+
+    pub fn to_16bit(self) -> &'static str {
+        match self {
+            Register::Rax => "%ax",
+            Register::Rbx => "%bx",
+            Register::Rcx => "%cx",
+            Register::Rdx => "%dx",
+            Register::Rsi => "%si",
+            Register::Rdi => "%di",
+            Register::R8 => "%r8w",
+            Register::R9 => "%r9w",
+            Register::R10 => "%r10w",
+            Register::R11 => "%r11w",
+            Register::R12 => "%r12w",
+            Register::R13 => "%r13w",
+            Register::R14 => "%r14w",
+            Register::R15 => "%r15w",
+        }
+    }
+
+    pub fn to_32bit(self) -> &'static str {
+        match self {
+            Register::Rax => "%eax",
+            Register::Rbx => "%ebx",
+            Register::Rcx => "%ecx",
+            Register::Rdx => "%edx",
+            Register::Rsi => "%esi",
+            Register::Rdi => "%edi",
+            Register::R8 => "%r8d",
+            Register::R9 => "%r9d",
+            Register::R10 => "%r10d",
+            Register::R11 => "%r11d",
+            Register::R12 => "%r12d",
+            Register::R13 => "%r13d",
+            Register::R14 => "%r14d",
+            Register::R15 => "%r15d",
+        }
+    }
 }
 
 const ALL_REGISTERS: &[Register] = {
@@ -124,7 +168,7 @@ impl RegAlloc {
             self.free.last().unwrap()
         };
 
-        Ok(self.alloc_reg(*victim, out)?)
+        self.alloc_reg(*victim, out)
     }
 
     pub fn alloc_reg(
@@ -141,7 +185,7 @@ impl RegAlloc {
         let spill_slot = self.next_slot;
         if spill_slot % 16 == -8 {
             // Make more space for spillage, we dont reuse stack space :0
-            self.emit_instr(&format!("subq $16, %rsp"), out)?;
+            self.emit_instr("subq $16, %rsp", out)?;
             if let Some(slot) = self.spill_activity.last_mut() {
                 *slot += 16;
             }
@@ -152,7 +196,7 @@ impl RegAlloc {
         self.spilled.get_mut(&reg).unwrap().push(spill_slot);
         self.emit_instr(&format!("movq {reg}, {spill_slot}(%rbp)"), out)?;
 
-        return Ok(reg);
+        Ok(reg)
     }
 
     pub fn free(&mut self, reg: Register, out: &mut BufWriter<File>) -> Result<(), Diagnostic> {
@@ -173,13 +217,13 @@ impl RegAlloc {
     pub fn save_registers(
         &mut self,
         dstr: Register,
-        saved: &Vec<Register>,
+        saved: &[Register],
         out: &mut BufWriter<File>,
     ) -> Result<(), Diagnostic> {
         self.spill_activity.push(0);
 
         for reg in saved {
-            let reg_free = self.free.contains(&reg);
+            let reg_free = self.free.contains(reg);
             if dstr != *reg && !reg_free {
                 self.emit_instr(&format!("pushq {reg}"), out)?;
             }
@@ -191,7 +235,7 @@ impl RegAlloc {
     pub fn load_registers(
         &mut self,
         dstr: Register,
-        saved: &Vec<Register>,
+        saved: &[Register],
         out: &mut BufWriter<File>,
     ) -> Result<(), Diagnostic> {
         // Any stack activity from spilling, we must readjust before we pop again
@@ -200,8 +244,8 @@ impl RegAlloc {
             self.emit_instr(&format!("addq ${spilled}, %rsp"), out)?
         }
 
-        for reg in saved.into_iter().rev() {
-            let reg_free = self.free.contains(&reg);
+        for reg in saved.iter().rev() {
+            let reg_free = self.free.contains(reg);
             if dstr != *reg && !reg_free {
                 self.emit_instr(&format!("popq {reg}"), out)?;
             }
@@ -221,10 +265,7 @@ impl RegAlloc {
     }
 
     fn emit(&self, line: &str, out: &mut BufWriter<File>) -> Result<(), Diagnostic> {
-        writeln!(out, "{line}").map_err(|_| Diagnostic {
-            line: -1,
-            kind: DiagnosticKind::WriteErr,
-        })
+        writeln!(out, "{line}").map_err(|_| Diagnostic::unspanned(DiagnosticKind::WriteErr))
     }
 }
 
@@ -232,21 +273,26 @@ pub struct Codegen<'ctx> {
     ctx: &'ctx Context,
     out: BufWriter<File>,
     ra: RegAlloc,
+    next_label: usize,
+    traps: Vec<(String, String)>, // Label of each runtime check failure and its message
+    strings: Vec<(String, String)>, // Label of each string literal and its contents
 }
 
 impl<'ctx> Codegen<'ctx> {
     pub fn try_new(ctx: &'ctx Context) -> Result<Self, Diagnostic> {
-        let file = File::create(&ctx.out_path).map_err(|_| Diagnostic {
-            line: -1,
-            kind: DiagnosticKind::FailedOutOpen {
+        let file = File::create(&ctx.out_path).map_err(|_| {
+            Diagnostic::unspanned(DiagnosticKind::FailedOutOpen {
                 path: ctx.out_path.to_owned(),
-            },
+            })
         })?;
         let out = BufWriter::new(file);
         Ok(Self {
             ctx,
             out,
             ra: RegAlloc::new(0),
+            next_label: 0,
+            traps: vec![],
+            strings: vec![],
         })
     }
 
@@ -257,7 +303,10 @@ impl<'ctx> Codegen<'ctx> {
         }
 
         for stmt in &ast.top {
-            match self.gen_statement(stmt) {
+            let StmtKind::FuncDecl(info) = &stmt.kind else {
+                continue;
+            };
+            match self.gen_func(info) {
                 Ok(_) => {}
                 Err(diag) => {
                     self.ctx.diags.borrow_mut().report(diag);
@@ -266,6 +315,22 @@ impl<'ctx> Codegen<'ctx> {
             }
         }
 
+        if let Err(diag) = self.gen_traps() {
+            self.ctx.diags.borrow_mut().report(diag);
+            return;
+        }
+
+        // Globals may point at string bytes, which are only written out after them
+        if let Err(diag) = self.gen_globals(ast) {
+            self.ctx.diags.borrow_mut().report(diag);
+            return;
+        }
+
+        if let Err(diag) = self.gen_strings() {
+            self.ctx.diags.borrow_mut().report(diag);
+            return;
+        }
+
         let note = "\n# comply with g++ warning\n.section .note.GNU-stack,\"\",@progbits";
         if self.emit(note).is_err() {
             self.report_write_error();
@@ -285,6 +350,8 @@ impl<'ctx> Codegen<'ctx> {
             StmtKind::FuncDecl(info) => self.gen_func(info),
             StmtKind::Block(stmts) => self.gen_block(stmts),
             StmtKind::VarDecl(info) => self.gen_var_decl(info),
+            StmtKind::ConstDecl(_) => Ok(()), // Inlined wherever it's used
+            StmtKind::StructDecl(_) => Ok(()), // Only a layout, nothing to emit
             StmtKind::If(info) => self.gen_if(info),
             StmtKind::While(info) => self.gen_while(info),
             StmtKind::Return(info) => self.gen_return(info),
@@ -324,11 +391,28 @@ impl<'ctx> Codegen<'ctx> {
         if !func_info.params.is_empty() {
             self.emit_instr("# Move register paramaters onto variable stack slot")?;
         }
-        for (index, id) in func_info.params.iter().enumerate().take(6) {
-            let reg = self.index_to_param_reg(index);
+        if let Some(slot) = func_info.return_slot {
+            self.emit_instr(&format!("movq %rdi, {slot}(%rbp)"))?;
+        }
+        let (locs, _) = abi::arg_locations(&symbols, &func_info.param_tys, &func_info.return_ty);
+        for (id, loc) in func_info.params.iter().zip(locs) {
+            // Stack parameters are already in place
+            let ArgLoc::Regs(first) = loc else {
+                continue;
+            };
 
-            let offset = self.symbols().var_info(*id).offset;
-            self.emit_instr(&format!("movq {reg}, {offset}(%rbp)"))?;
+            let ty = self.symbols().var_type(*id);
+            let operand = self.var_operand(*id);
+            if self.symbols().is_aggregate(&ty) {
+                // Small structs arrive split over consecutive registers, one eightbyte each
+                let eightbytes = self.symbols().size_of(&ty).div_ceil(8);
+                for k in 0..eightbytes {
+                    let reg = self.index_to_param_reg(first + k);
+                    self.emit_instr(&format!("movq {reg}, {}", offset_operand(&operand, k * 8)))?;
+                }
+            } else {
+                self.emit_store(&ty, self.index_to_param_reg(first), &operand)?;
+            }
         }
 
         self.emit_blank()?;
@@ -353,9 +437,8 @@ impl<'ctx> Codegen<'ctx> {
         let var_id = info.id.unwrap();
         let expr = &info.expr;
         let cr = self.gen_expr(expr)?;
-        let store_offset = self.symbols().var_info(var_id).offset;
 
-        self.emit_instr(&format!("movq {cr}, {store_offset}(%rbp)"))?;
+        self.emit_store(expr_ty(expr), cr, &self.var_operand(var_id))?;
 
         self.ra.free(cr, &mut self.out)?;
         Ok(())
@@ -425,14 +508,53 @@ impl<'ctx> Codegen<'ctx> {
 
     fn gen_return(&mut self, info: &ReturnInfo) -> Result<(), Diagnostic> {
         let ReturnInfo { id, expr } = info;
-        let cr = self.gen_expr(expr)?;
-        self.emit_movq_reg(cr, Register::Rax)?;
+        let cr = match expr {
+            Some(expr) if self.symbols().is_aggregate(expr_ty(expr)) => {
+                let cr = self.gen_expr(expr)?;
+                self.gen_return_struct(id.unwrap(), expr_ty(expr), cr)?;
+                Some(cr)
+            }
+            Some(expr) => {
+                let cr = self.gen_expr(expr)?;
+                self.emit_movq_reg(cr, Register::Rax)?;
+                Some(cr)
+            }
+            None => None,
+        };
         let label = self.epilogue_label(id.unwrap());
         self.emit_instr(&format!("jmp {label}"))?;
-        self.ra.free(cr, &mut self.out)?;
+        if let Some(cr) = cr {
+            self.ra.free(cr, &mut self.out)?;
+        }
         Ok(())
     }
 
+    // Small structs go back in %rax and %rdx, larger ones are copied to where the caller asked.
+    // Nothing else is live once the function returns, so %rsi is free to hold the source
+    fn gen_return_struct(
+        &mut self,
+        func_id: SymbolID,
+        ty: &ResolvedType,
+        cr: Register,
+    ) -> Result<(), Diagnostic> {
+        let size = self.symbols().size_of(ty);
+        let return_slot = self.symbols().func_info(func_id).return_slot;
+        self.emit_movq_reg(cr, Register::Rsi)?;
+
+        match return_slot {
+            Some(slot) => {
+                self.emit_instr(&format!("movq {slot}(%rbp), %rax"))?;
+                self.emit_copy(size, Register::Rsi, "(%rax)")
+            }
+            None => {
+                self.emit_instr("subq $16, %rsp")?;
+                self.emit_copy(size, Register::Rsi, "8(%rsp)")?;
+                self.emit_instr("popq %rax")?;
+                self.emit_instr("popq %rdx")
+            }
+        }
+    }
+
     fn gen_continue(&mut self, id: LoopID) -> Result<(), Diagnostic> {
         let (loop_start, _) = self.loop_labels(id);
         self.emit_instr(&format!("jmp {loop_start}"))?;
@@ -447,30 +569,203 @@ impl<'ctx> Codegen<'ctx> {
 
     fn gen_expr(&mut self, expr: &Expr) -> Result<Register, Diagnostic> {
         match &expr.kind {
-            ExprKind::Literal(val) => self.gen_expr_literal(*val),
+            ExprKind::Literal(info) => self.gen_expr_literal(info.value as i64),
+            ExprKind::Bool(val) => self.gen_expr_literal(*val as i64),
+            ExprKind::Char(val) => self.gen_expr_literal(*val as i64),
+            ExprKind::Str(contents) => self.gen_expr_str(contents),
             ExprKind::Var(id) => self.gen_expr_var(id.unwrap()),
             ExprKind::Func(info) => self.gen_expr_func(info),
-            ExprKind::UnOp(info) => self.gen_expr_unop(info),
-            ExprKind::BinOp(info) => self.gen_expr_binop(info),
+            ExprKind::UnOp(info) => self.gen_expr_unop(info, expr.token.span),
+            ExprKind::BinOp(info) => self.gen_expr_binop(info, expr.token.span),
+            ExprKind::Cast(info) => self.gen_expr_cast(info, expr_ty(expr)),
+            ExprKind::CompoundAssign(info) => self.gen_expr_compound_assign(info, expr.token.span),
+            ExprKind::Index(info) => self.gen_expr_index(info, expr_ty(expr)),
+            ExprKind::Array(info) => self.gen_expr_array(info, expr_ty(expr)),
+            ExprKind::ArrayRepeat(info) => self.gen_expr_array_repeat(info, expr_ty(expr)),
+            ExprKind::Field(info) => self.gen_expr_field(info, expr_ty(expr)),
+            ExprKind::StructLit(info) => self.gen_expr_struct_lit(info, expr_ty(expr)),
         }
     }
 
+    fn gen_expr_field(
+        &mut self,
+        info: &FieldInfo,
+        ty: &ResolvedType,
+    ) -> Result<Register, Diagnostic> {
+        let (addr, r) = self.gen_field_addr(info)?;
+        self.emit_load(ty, &addr, r)?;
+        Ok(r)
+    }
+
+    // Structs evaluate to their address, so the field sits at a fixed offset from it
+    fn gen_field_addr(&mut self, info: &FieldInfo) -> Result<(String, Register), Diagnostic> {
+        let FieldInfo { base, offset } = info;
+        let offset = offset.expect("fields are resolved by semantic analysis");
+        let r = self.gen_expr(base)?;
+        Ok((offset_operand(&format!("({r})"), offset), r))
+    }
+
+    // Like array literals, each field is stored straight into the literal's slot
+    fn gen_expr_struct_lit(
+        &mut self,
+        info: &StructLitInfo,
+        ty: &ResolvedType,
+    ) -> Result<Register, Diagnostic> {
+        let StructLitInfo { fields, slot } = info;
+        let slot = slot.expect("struct literals in functions have a slot");
+
+        for init in fields {
+            let offset = slot + init.offset.unwrap() as i64;
+            let field_ty = self
+                .symbols()
+                .field(ty, &init.token.lexeme)
+                .expect("fields are resolved by semantic analysis")
+                .ty
+                .clone();
+            let er = self.gen_expr(&init.expr)?;
+            self.emit_store(&field_ty, er, &format!("{offset}(%rbp)"))?;
+            self.ra.free(er, &mut self.out)?;
+        }
+
+        let r = self.ra.alloc_any(&mut self.out)?;
+        self.emit_instr(&format!("leaq {slot}(%rbp), {r}"))?;
+        Ok(r)
+    }
+
+    fn gen_expr_index(
+        &mut self,
+        info: &IndexInfo,
+        ty: &ResolvedType,
+    ) -> Result<Register, Diagnostic> {
+        let r = self.gen_index_addr(info)?;
+        self.emit_load(ty, &format!("({r})"), r)?;
+        Ok(r)
+    }
+
+    // Leaves the address of the indexed element in the returned register
+    fn gen_index_addr(&mut self, info: &IndexInfo) -> Result<Register, Diagnostic> {
+        let IndexInfo { base, index } = info;
+        let is_str = self.symbols().is_str(expr_ty(base));
+        let elem_size = match self.symbols().elem_type(expr_ty(base)) {
+            Some((elem_ty, _)) => self.symbols().size_of(&elem_ty),
+            None if is_str => 1,
+            None => unreachable!("only arrays and strings are indexed"),
+        };
+
+        // Arrays evaluate to their address, strings to the address of their pointer to the bytes
+        let br = self.gen_expr(base)?;
+        if is_str {
+            self.emit_instr(&format!("movq ({br}), {br}"))?;
+        }
+        let ir = self.gen_expr(index)?;
+        if matches!(elem_size, 1 | 2 | 4 | 8) {
+            self.emit_instr(&format!("leaq ({br},{ir},{elem_size}), {br}"))?;
+        } else {
+            self.emit_instr(&format!("imulq ${elem_size}, {ir}"))?;
+            self.emit_instr(&format!("addq {ir}, {br}"))?;
+        }
+        self.ra.free(ir, &mut self.out)?;
+        Ok(br)
+    }
+
+    // Each element is stored straight into the literal's slot
+    fn gen_expr_array(
+        &mut self,
+        info: &ArrayInfo,
+        ty: &ResolvedType,
+    ) -> Result<Register, Diagnostic> {
+        let ArrayInfo { elems, slot } = info;
+        let slot = slot.expect("array literals in functions have a slot");
+        let (elem_ty, _) = self.symbols().elem_type(ty).unwrap();
+        let elem_size = self.symbols().size_of(&elem_ty) as i64;
+
+        for (index, elem) in elems.iter().enumerate() {
+            let er = self.gen_expr(elem)?;
+            let offset = slot + index as i64 * elem_size;
+            self.emit_store(&elem_ty, er, &format!("{offset}(%rbp)"))?;
+            self.ra.free(er, &mut self.out)?;
+        }
+
+        let r = self.ra.alloc_any(&mut self.out)?;
+        self.emit_instr(&format!("leaq {slot}(%rbp), {r}"))?;
+        Ok(r)
+    }
+
+    // The value is evaluated once, then copied into every element by a loop
+    fn gen_expr_array_repeat(
+        &mut self,
+        info: &ArrayRepeatInfo,
+        ty: &ResolvedType,
+    ) -> Result<Register, Diagnostic> {
+        let ArrayRepeatInfo { value, count, slot } = info;
+        let slot = slot.expect("array literals in functions have a slot");
+        let (elem_ty, _) = self.symbols().elem_type(ty).unwrap();
+        let elem_size = self.symbols().size_of(&elem_ty);
+        let (fill_start, fill_end) = (self.fresh_label("fill_start"), self.fresh_label("fill_end"));
+
+        let vr = self.gen_expr(value)?;
+        let pr = self.ra.alloc_any(&mut self.out)?;
+        let cr = self.ra.alloc_any(&mut self.out)?;
+        self.emit_instr(&format!("leaq {slot}(%rbp), {pr}"))?;
+        self.emit_instr(&format!("movq ${count}, {cr}"))?;
+
+        self.emit_label(&fill_start)?;
+        self.emit_instr(&format!("testq {cr}, {cr}"))?;
+        self.emit_instr(&format!("je {fill_end}"))?;
+        self.emit_store(&elem_ty, vr, &format!("({pr})"))?;
+        self.emit_instr(&format!("addq ${elem_size}, {pr}"))?;
+        self.emit_instr(&format!("decq {cr}"))?;
+        self.emit_instr(&format!("jmp {fill_start}"))?;
+        self.emit_label(&fill_end)?;
+
+        self.ra.free(cr, &mut self.out)?;
+        self.ra.free(pr, &mut self.out)?;
+        self.emit_instr(&format!("leaq {slot}(%rbp), {vr}"))?;
+        Ok(vr)
+    }
+
+    // The value is already extended according to its old type, so truncating or extending it
+    // to the new one is the same as normalizing it for the new type
+    fn gen_expr_cast(
+        &mut self,
+        info: &CastInfo,
+        ty: &ResolvedType,
+    ) -> Result<Register, Diagnostic> {
+        let cr = self.gen_expr(&info.expr)?;
+        self.emit_normalize(ty, cr)?;
+        Ok(cr)
+    }
+
     fn gen_expr_literal(&mut self, val: i64) -> Result<Register, Diagnostic> {
         let r = self.ra.alloc_any(&mut self.out)?;
         self.emit_instr(&format!("movq ${val}, {r}"))?;
         Ok(r)
     }
 
+    // Literals are emitted once the functions are done, the value is the address of the string
+    fn gen_expr_str(&mut self, contents: &str) -> Result<Register, Diagnostic> {
+        let label = self.fresh_label("str");
+        self.strings.push((label.clone(), contents.to_owned()));
+
+        let r = self.ra.alloc_any(&mut self.out)?;
+        self.emit_instr(&format!("leaq {label}(%rip), {r}"))?;
+        Ok(r)
+    }
+
     fn gen_expr_var(&mut self, id: SymbolID) -> Result<Register, Diagnostic> {
-        let load_offset = self.symbols().var_info(id).offset;
+        if let Some(value) = self.symbols().const_value(id) {
+            return self.gen_expr_literal(value);
+        }
+
+        let ty = self.symbols().var_type(id);
         let r = self.ra.alloc_any(&mut self.out)?;
-        self.emit_instr(&format!("movq {load_offset}(%rbp), {r}"))?;
+        self.emit_load(&ty, &self.var_operand(id), r)?;
         Ok(r)
     }
 
     // Do you still think you shouldnt use a real IR?
     fn gen_expr_func(&mut self, info: &FuncCallInfo) -> Result<Register, Diagnostic> {
-        let FuncCallInfo { id, args } = info;
+        let FuncCallInfo { id, args, slot } = info;
         let id = id.unwrap();
 
         self.emit_blank()?;
@@ -491,42 +786,84 @@ impl<'ctx> Codegen<'ctx> {
             arg_regs.push(self.gen_expr(expr)?)
         }
 
-        const MAX_REGISTER_PARAMS: usize = 6;
-        let mid = arg_regs.len().min(MAX_REGISTER_PARAMS);
-        let (register_params, stack_params) = arg_regs.split_at(mid);
-
-        let total_param_offset = if stack_params.len() % 2 == 0 {
-            stack_params.len() * 8
-        } else {
-            // odd number of params we must pad
-            self.emit_instr("subq $8, %rsp")?;
-            stack_params.len() * 8 + 8
+        let (locs, stack_bytes) = {
+            let symbols = self.symbols();
+            let func_info = symbols.func_info(id);
+            abi::arg_locations(&symbols, &func_info.param_tys, &func_info.return_ty)
         };
 
-        // now we handle the allocated registers in reverse
-        for reg in stack_params.iter().rev() {
-            self.emit_instr(&format!("pushq {reg}"))?;
-            self.ra.free(*reg, &mut self.out)?;
+        // Room for the stack arguments, padded so the call stays 16 byte aligned
+        let total_param_offset = stack_bytes.next_multiple_of(16);
+        if total_param_offset > 0 {
+            self.emit_instr(&format!("subq ${total_param_offset}, %rsp"))?;
         }
 
+        // Arguments are handled in reverse so their registers are freed in order. Stack
+        // arguments are written into place, register arguments are pushed to be popped into
+        // their registers afterwards
         // TODO: Having to push and pop like this really sucks but i dont have enough
         // trust in my allocater to break the cycles with a temporary reliably
-        for (index, reg) in register_params.iter().enumerate().rev() {
-            if *reg != self.index_to_param_reg(index) {
-                self.emit_instr(&format!("pushq {reg}"))?;
+        let mut pushed = 0;
+        for ((reg, expr), loc) in arg_regs.iter().zip(args).zip(&locs).rev() {
+            let ty = expr_ty(expr);
+            let size = self.symbols().size_of(ty);
+            let is_aggregate = self.symbols().is_aggregate(ty);
+
+            match *loc {
+                // The copy saves a scratch register on the stack first, hence the extra 8
+                ArgLoc::Stack(offset) if is_aggregate => {
+                    let dst = format!("{}(%rsp)", offset + pushed + 8);
+                    self.emit_copy(size, *reg, &dst)?;
+                }
+                ArgLoc::Stack(offset) => {
+                    self.emit_instr(&format!("movq {reg}, {}(%rsp)", offset + pushed))?;
+                }
+                ArgLoc::Regs(_) if is_aggregate => {
+                    let bytes = size.next_multiple_of(8);
+                    self.emit_instr(&format!("subq ${bytes}, %rsp"))?;
+                    self.emit_copy(size, *reg, "8(%rsp)")?;
+                    pushed += bytes;
+                }
+                ArgLoc::Regs(_) => {
+                    self.emit_instr(&format!("pushq {reg}"))?;
+                    pushed += 8;
+                }
             }
             self.ra.free(*reg, &mut self.out)?;
         }
 
-        for i in 0..register_params.len() {
-            let param_reg = self.index_to_param_reg(i);
-            if register_params[i] != param_reg {
+        for (expr, loc) in args.iter().zip(&locs) {
+            let ArgLoc::Regs(first) = *loc else {
+                continue;
+            };
+            let eightbytes = self.symbols().size_of(expr_ty(expr)).div_ceil(8).max(1);
+            for k in 0..eightbytes {
+                let param_reg = self.index_to_param_reg(first + k);
                 self.emit_instr(&format!("popq {param_reg}"))?;
             }
         }
 
+        // A struct returned in memory is written to the slot through the hidden first argument
+        let return_ty = self.symbols().func_info(id).return_ty.clone();
+        let returns_in_memory = abi::returns_in_memory(&self.symbols(), &return_ty);
+        if returns_in_memory {
+            self.emit_instr(&format!("leaq {}(%rbp), %rdi", slot.unwrap()))?;
+        }
+
         self.emit_instr(&format!("call {}", self.mangle(id)))?;
-        self.emit_movq_reg(Register::Rax, r)?;
+        match slot {
+            Some(slot) => {
+                // Structs returned in registers are stored to the slot to get an address
+                if !returns_in_memory {
+                    self.emit_instr(&format!("movq %rax, {slot}(%rbp)"))?;
+                    if self.symbols().size_of(&return_ty) > 8 {
+                        self.emit_instr(&format!("movq %rdx, {}(%rbp)", slot + 8))?;
+                    }
+                }
+                self.emit_instr(&format!("leaq {slot}(%rbp), {r}"))?;
+            }
+            None => self.emit_movq_reg(Register::Rax, r)?,
+        }
 
         // clear all the stack params that we pushed
         if total_param_offset > 0 {
@@ -542,39 +879,170 @@ impl<'ctx> Codegen<'ctx> {
         Ok(r)
     }
 
-    fn gen_expr_unop(&mut self, info: &UnOpInfo) -> Result<Register, Diagnostic> {
+    fn gen_expr_unop(&mut self, info: &UnOpInfo, span: SourceSpan) -> Result<Register, Diagnostic> {
         let UnOpInfo { op, expr } = info;
+
+        // The operand is only located, not evaluated
+        if matches!(op, UnOpKind::AddrOf) {
+            let (addr, ar) = self.gen_addr(expr)?;
+            let r = match ar {
+                Some(ar) => ar,
+                None => self.ra.alloc_any(&mut self.out)?,
+            };
+            self.emit_instr(&format!("leaq {addr}, {r}"))?;
+            return Ok(r);
+        }
+
         let cr = self.gen_expr(expr)?;
         match op {
-            UnOpKind::Neg => self.emit_instr(&format!("negq {cr}"))?,
+            UnOpKind::Neg if self.checked() => {
+                self.emit_instr(&format!("negq {cr}"))?;
+                self.emit_overflow_check(expr_ty(expr), cr, Some("jo"), span, "negate")?;
+            }
+            UnOpKind::Neg => {
+                self.emit_instr(&format!("negq {cr}"))?;
+                self.emit_normalize(expr_ty(expr), cr)?;
+            }
+            UnOpKind::BitNot => {
+                self.emit_instr(&format!("notq {cr}"))?;
+                self.emit_normalize(expr_ty(expr), cr)?;
+            }
             UnOpKind::Not => {
                 self.emit_instr(&format!("testq {cr}, {cr}"))?;
                 self.emit_instr(&format!("sete {}", cr.to_8bit()))?;
                 self.emit_instr(&format!("movzbq {}, {cr}", cr.to_8bit()))?;
             }
+            UnOpKind::Deref => {
+                let pointee = self.symbols().pointee_type(expr_ty(expr)).unwrap();
+                self.emit_load(&pointee, &format!("({cr})"), cr)?;
+            }
+            UnOpKind::AddrOf => unreachable!("handled before evaluating the operand"),
         };
         Ok(cr)
     }
 
-    fn gen_expr_binop(&mut self, info: &BinOpInfo) -> Result<Register, Diagnostic> {
+    fn gen_expr_binop(
+        &mut self,
+        info: &BinOpInfo,
+        span: SourceSpan,
+    ) -> Result<Register, Diagnostic> {
         let BinOpInfo { op, lhs, rhs } = info;
 
         // Explicitly handle assignment case
         if matches!(op, BinOpKind::Assign) {
-            let ExprKind::Var(id) = lhs.kind else {
-                panic!("gen assign w/o var");
-            };
-
-            let store_offset = self.symbols().var_info(id.unwrap()).offset;
+            let (addr, ar) = self.gen_addr(lhs)?;
             let cr = self.gen_expr(rhs)?;
 
-            self.emit_instr(&format!("movq {cr}, {store_offset}(%rbp)"))?;
-            return Ok(cr);
+            self.emit_store(expr_ty(lhs), cr, &addr)?;
+            return self.free_addr_reg(ar, cr);
+        }
+
+        if matches!(op, BinOpKind::And | BinOpKind::Or) {
+            return self.gen_short_circuit(*op, lhs, rhs);
         }
 
         let lhsr = self.gen_expr(lhs)?;
         let rhsr = self.gen_expr(rhs)?;
 
+        // Operands always share a type, so the left one decides signedness and width
+        self.gen_binop_regs(*op, lhsr, rhsr, expr_ty(lhs), span)?;
+
+        self.ra.free(rhsr, &mut self.out)?;
+        Ok(lhsr)
+    }
+
+    // Reads the target once for both the load and the store. The right side is evaluated
+    // before the target's current value is loaded
+    fn gen_expr_compound_assign(
+        &mut self,
+        info: &CompoundAssignInfo,
+        span: SourceSpan,
+    ) -> Result<Register, Diagnostic> {
+        let CompoundAssignInfo { op, lhs, rhs } = info;
+        let ty = expr_ty(lhs);
+
+        let (addr, ar) = self.gen_addr(lhs)?;
+        let rhsr = self.gen_expr(rhs)?;
+        let lhsr = self.ra.alloc_any(&mut self.out)?;
+        self.emit_load(ty, &addr, lhsr)?;
+
+        self.gen_binop_regs(*op, lhsr, rhsr, ty, span)?;
+        self.emit_store(ty, lhsr, &addr)?;
+
+        // Hand back the register allocated first so they are freed in order
+        self.emit_movq_reg(lhsr, rhsr)?;
+        self.ra.free(lhsr, &mut self.out)?;
+        self.free_addr_reg(ar, rhsr)
+    }
+
+    // Memory operand for an assignable expression, along with the register holding the address
+    // when it had to be computed
+    fn gen_addr(&mut self, expr: &Expr) -> Result<(String, Option<Register>), Diagnostic> {
+        match &expr.kind {
+            ExprKind::Var(id) => Ok((self.var_operand(id.unwrap()), None)),
+            ExprKind::Index(info) => {
+                let r = self.gen_index_addr(info)?;
+                Ok((format!("({r})"), Some(r)))
+            }
+            ExprKind::Field(info) => {
+                let (addr, r) = self.gen_field_addr(info)?;
+                Ok((addr, Some(r)))
+            }
+            ExprKind::UnOp(UnOpInfo {
+                op: UnOpKind::Deref,
+                expr,
+            }) => {
+                let r = self.gen_expr(expr)?;
+                Ok((format!("({r})"), Some(r)))
+            }
+            _ => panic!("gen address of non-lvalue"),
+        }
+    }
+
+    // Moves the result of an assignment into the address register, if there is one, so the
+    // registers are still freed in the order they were allocated
+    fn free_addr_reg(
+        &mut self,
+        ar: Option<Register>,
+        result: Register,
+    ) -> Result<Register, Diagnostic> {
+        let Some(ar) = ar else {
+            return Ok(result);
+        };
+        self.emit_movq_reg(result, ar)?;
+        self.ra.free(result, &mut self.out)?;
+        Ok(ar)
+    }
+
+    // Combines two evaluated operands of type `operand_ty`, leaving the result in lhsr
+    fn gen_binop_regs(
+        &mut self,
+        op: BinOpKind,
+        lhsr: Register,
+        rhsr: Register,
+        operand_ty: &ResolvedType,
+        span: SourceSpan,
+    ) -> Result<(), Diagnostic> {
+        // Pointers move by whole elements. The offset may be negative, so nothing is checked
+        let pointee = self.symbols().pointee_type(operand_ty);
+        if let Some(pointee) = pointee
+            && matches!(op, BinOpKind::Add | BinOpKind::Sub)
+        {
+            let elem_size = self.symbols().size_of(&pointee);
+            if elem_size != 1 {
+                self.emit_instr(&format!("imulq ${elem_size}, {rhsr}"))?;
+            }
+            let instr = if matches!(op, BinOpKind::Add) {
+                "addq"
+            } else {
+                "subq"
+            };
+            return self.emit_instr(&format!("{instr} {rhsr}, {lhsr}"));
+        }
+
+        let (size, signed) = self.int_layout(operand_ty);
+        let checked = self.checked();
+
         let is_cmp = matches!(
             op,
             BinOpKind::Equals
@@ -591,45 +1059,393 @@ impl<'ctx> Codegen<'ctx> {
 
         let lhsr_8bit = lhsr.to_8bit();
         match op {
-            BinOpKind::Assign => unreachable!(),
+            BinOpKind::Assign | BinOpKind::And | BinOpKind::Or => unreachable!(),
             BinOpKind::Add => self.emit_instr(&format!("addq {rhsr}, {lhsr}"))?,
             BinOpKind::Sub => self.emit_instr(&format!("subq {rhsr}, {lhsr}"))?,
+            BinOpKind::Mult if checked && !signed && size == 8 => {
+                self.gen_checked_mul_unsigned(lhsr, rhsr, span)?
+            }
             BinOpKind::Mult => self.emit_instr(&format!("imulq {rhsr}, {lhsr}"))?,
-            BinOpKind::Div => {
-                self.ra
-                    .save_registers(lhsr, &vec![Register::Rax, Register::Rdx], &mut self.out)?;
-
-                self.emit_movq_reg(lhsr, Register::Rax)?;
-                //self.emit_instr(&format!("movq {lhsr}, %rax"))?;
-                self.emit_instr("cqto")?;
-                self.emit_instr(&format!("idivq {rhsr}"))?;
-                //self.emit_instr(&format!("movq %rax, {lhsr}"))?;
-                self.emit_movq_reg(Register::Rax, lhsr)?;
-
-                self.ra
-                    .load_registers(lhsr, &vec![Register::Rax, Register::Rdx], &mut self.out)?;
+            BinOpKind::Div | BinOpKind::Mod => {
+                let remainder = matches!(op, BinOpKind::Mod);
+                if checked {
+                    self.gen_div_guards(lhsr, rhsr, signed && size == 8, remainder, span)?;
+                }
+                self.gen_divmod(lhsr, rhsr, signed, remainder)?
             }
+            BinOpKind::BitAnd => self.emit_instr(&format!("andq {rhsr}, {lhsr}"))?,
+            BinOpKind::BitOr => self.emit_instr(&format!("orq {rhsr}, {lhsr}"))?,
+            BinOpKind::BitXor => self.emit_instr(&format!("xorq {rhsr}, {lhsr}"))?,
+            BinOpKind::Shl => self.gen_shift("salq", lhsr, rhsr)?,
+            BinOpKind::Shr if signed => self.gen_shift("sarq", lhsr, rhsr)?,
+            BinOpKind::Shr => self.gen_shift("shrq", lhsr, rhsr)?,
             BinOpKind::Equals => self.emit_instr(&format!("sete {lhsr_8bit}"))?,
             BinOpKind::NotEquals => self.emit_instr(&format!("setne {lhsr_8bit}"))?,
-            BinOpKind::LessThan => self.emit_instr(&format!("setl {lhsr_8bit}"))?,
-            BinOpKind::GreaterThan => self.emit_instr(&format!("setg {lhsr_8bit}"))?,
-            BinOpKind::LessEq => self.emit_instr(&format!("setle {lhsr_8bit}"))?,
-            BinOpKind::GreaterEq => self.emit_instr(&format!("setge {lhsr_8bit}"))?,
+            BinOpKind::LessThan if signed => self.emit_instr(&format!("setl {lhsr_8bit}"))?,
+            BinOpKind::GreaterThan if signed => self.emit_instr(&format!("setg {lhsr_8bit}"))?,
+            BinOpKind::LessEq if signed => self.emit_instr(&format!("setle {lhsr_8bit}"))?,
+            BinOpKind::GreaterEq if signed => self.emit_instr(&format!("setge {lhsr_8bit}"))?,
+            BinOpKind::LessThan => self.emit_instr(&format!("setb {lhsr_8bit}"))?,
+            BinOpKind::GreaterThan => self.emit_instr(&format!("seta {lhsr_8bit}"))?,
+            BinOpKind::LessEq => self.emit_instr(&format!("setbe {lhsr_8bit}"))?,
+            BinOpKind::GreaterEq => self.emit_instr(&format!("setae {lhsr_8bit}"))?,
+        };
+
+        // Jump taken on overflow of a full width operation, if its flags tell. Unsigned multiplies
+        // and divides were already checked while being carried out
+        let carry_or_overflow = if signed { "jo" } else { "jc" };
+        let overflow = match op {
+            BinOpKind::Add if checked => Some((Some(carry_or_overflow), "add")),
+            BinOpKind::Sub if checked => Some((Some(carry_or_overflow), "subtract")),
+            BinOpKind::Mult if checked => Some((signed.then_some("jo"), "multiply")),
+            BinOpKind::Div if checked => Some((None, "divide")),
+            _ => None,
         };
 
         if is_cmp {
             self.emit_instr(&format!("movzbq {lhsr_8bit}, {lhsr}"))?;
+        } else if let Some((jump, what)) = overflow {
+            self.emit_overflow_check(operand_ty, lhsr, jump, span, what)?;
+        } else {
+            // Narrow results wrap around like they would in a register of their own size
+            self.emit_normalize(operand_ty, lhsr)?;
         }
+        Ok(())
+    }
+
+    // idiv/div are hardwired to %rax and %rdx, so both are saved around the division and the
+    // divisor goes through the stack in case it lives in one of them
+    fn gen_divmod(
+        &mut self,
+        lhsr: Register,
+        rhsr: Register,
+        signed: bool,
+        remainder: bool,
+    ) -> Result<(), Diagnostic> {
+        self.emit_instr("pushq %rdx")?;
+        self.emit_instr("pushq %rax")?;
+        self.emit_instr(&format!("pushq {rhsr}"))?;
+
+        self.emit_movq_reg(lhsr, Register::Rax)?;
+        if signed {
+            self.emit_instr("cqto")?;
+            self.emit_instr("idivq (%rsp)")?;
+        } else {
+            self.emit_instr("xorl %edx, %edx")?;
+            self.emit_instr("divq (%rsp)")?;
+        }
+
+        let result = if remainder {
+            Register::Rdx
+        } else {
+            Register::Rax
+        };
+        self.emit_movq_reg(result, lhsr)?;
+        self.emit_instr("addq $8, %rsp")?;
+        self.emit_restore_rax_rdx(lhsr)
+    }
+
+    // Restores %rax and %rdx after a division or multiply, unless the result now lives there
+    fn emit_restore_rax_rdx(&mut self, result: Register) -> Result<(), Diagnostic> {
+        for reg in [Register::Rax, Register::Rdx] {
+            if reg == result {
+                self.emit_instr("addq $8, %rsp")?;
+            } else {
+                self.emit_instr(&format!("popq {reg}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    // imul only reports signed overflow, so full width unsigned products go through mul, which
+    // sets the carry flag when the high half of the product is needed
+    fn gen_checked_mul_unsigned(
+        &mut self,
+        lhsr: Register,
+        rhsr: Register,
+        span: SourceSpan,
+    ) -> Result<(), Diagnostic> {
+        let trap = self.trap_label(span, "multiply with overflow");
+        self.emit_instr("pushq %rdx")?;
+        self.emit_instr("pushq %rax")?;
+        self.emit_instr(&format!("pushq {rhsr}"))?;
+
+        self.emit_movq_reg(lhsr, Register::Rax)?;
+        self.emit_instr("mulq (%rsp)")?;
+        self.emit_instr(&format!("jc {trap}"))?;
+
+        self.emit_movq_reg(Register::Rax, lhsr)?;
+        self.emit_instr("addq $8, %rsp")?;
+        self.emit_restore_rax_rdx(lhsr)
+    }
+
+    // Dividing by zero always traps. So does the one signed division whose result doesn't fit,
+    // the most negative value divided by -1, which only needs checking at full width since
+    // narrower types are caught by the overflow check afterwards
+    fn gen_div_guards(
+        &mut self,
+        lhsr: Register,
+        rhsr: Register,
+        full_width_signed: bool,
+        remainder: bool,
+        span: SourceSpan,
+    ) -> Result<(), Diagnostic> {
+        let (zero_message, overflow_message) = if remainder {
+            (
+                "calculate the remainder with a divisor of zero",
+                "calculate the remainder with overflow",
+            )
+        } else {
+            ("divide by zero", "divide with overflow")
+        };
+
+        let zero_trap = self.trap_label(span, zero_message);
+        self.emit_instr(&format!("testq {rhsr}, {rhsr}"))?;
+        self.emit_instr(&format!("je {zero_trap}"))?;
+        if !full_width_signed {
+            return Ok(());
+        }
+
+        // Negating a copy of the dividend overflows exactly when it is the most negative value
+        let overflow_trap = self.trap_label(span, overflow_message);
+        let div_ok = self.fresh_label("div_ok");
+        self.emit_instr(&format!("cmpq $-1, {rhsr}"))?;
+        self.emit_instr(&format!("jne {div_ok}"))?;
+        self.emit_instr(&format!("pushq {lhsr}"))?;
+        self.emit_instr("negq (%rsp)")?;
+        self.emit_instr("leaq 8(%rsp), %rsp")?;
+        self.emit_instr(&format!("jo {overflow_trap}"))?;
+        self.emit_label(&div_ok)
+    }
 
+    // Traps if the result of an arithmetic operation doesn't fit its type. Narrow types are
+    // checked by whether the value survives being wrapped to the type, which also leaves it
+    // normalized. Full width results rely on the flags the operation left, through `jump`
+    fn emit_overflow_check(
+        &mut self,
+        ty: &ResolvedType,
+        reg: Register,
+        jump: Option<&str>,
+        span: SourceSpan,
+        what: &str,
+    ) -> Result<(), Diagnostic> {
+        let (size, _) = self.int_layout(ty);
+        let message = format!("{what} with overflow");
+        if size < 8 {
+            let trap = self.trap_label(span, &message);
+            self.emit_instr(&format!("pushq {reg}"))?;
+            self.emit_normalize(ty, reg)?;
+            self.emit_instr(&format!("cmpq (%rsp), {reg}"))?;
+            self.emit_instr("leaq 8(%rsp), %rsp")?;
+            return self.emit_instr(&format!("jne {trap}"));
+        }
+
+        if let Some(jump) = jump {
+            let trap = self.trap_label(span, &message);
+            self.emit_instr(&format!("{jump} {trap}"))?;
+        }
+        Ok(())
+    }
+
+    // Every failed check jumps to its own stub, which writes the check's message to stderr
+    // through the shared trap routine before exiting
+    fn gen_traps(&mut self) -> Result<(), Diagnostic> {
+        if self.traps.is_empty() {
+            return Ok(());
+        }
+
+        let traps = std::mem::take(&mut self.traps);
+        self.emit_blank()?;
+        for (label, message) in &traps {
+            self.emit_label(label)?;
+            self.emit_instr(&format!("leaq {label}_msg(%rip), %rsi"))?;
+            self.emit_instr(&format!("movq ${}, %rdx", message.len() + 1))?;
+            self.emit_instr("jmp _crsnt_trap")?;
+        }
+
+        self.emit_blank()?;
+        self.emit_label("_crsnt_trap")?;
+        self.emit_instr("movq $1, %rax # write")?;
+        self.emit_instr("movq $2, %rdi # stderr")?;
+        self.emit_instr("syscall")?;
+        self.emit_instr("movq $231, %rax # exit_group")?;
+        self.emit_instr(&format!("movq ${TRAP_EXIT_CODE}, %rdi"))?;
+        self.emit_instr("syscall")?;
+
+        self.emit_blank()?;
+        self.emit(".section .rodata")?;
+        for (label, message) in &traps {
+            self.emit_label(&format!("{label}_msg"))?;
+            self.emit_instr(&format!(".ascii \"{message}\\n\""))?;
+        }
+        Ok(())
+    }
+
+    // The bytes go in .rodata. The pointer to them needs a relocation when linked as PIE, so
+    // the values themselves go in .data.rel.ro, which is made read only once that's done
+    fn gen_strings(&mut self) -> Result<(), Diagnostic> {
+        if self.strings.is_empty() {
+            return Ok(());
+        }
+
+        let strings = std::mem::take(&mut self.strings);
+        self.emit_blank()?;
+        self.emit(".section .rodata")?;
+        for (label, contents) in &strings {
+            self.emit_label(&format!("{label}_bytes"))?;
+            self.emit_bytes(contents.as_bytes())?;
+        }
+
+        self.emit_blank()?;
+        self.emit(".section .data.rel.ro")?;
+        self.emit_instr(".balign 8")?;
+        for (label, contents) in &strings {
+            self.emit_label(label)?;
+            self.emit_instr(&format!(".quad {label}_bytes"))?;
+            self.emit_instr(&format!(".quad {}", contents.len()))?;
+        }
+        Ok(())
+    }
+
+    // Label to jump to when a runtime check at `span` fails
+    fn trap_label(&mut self, span: SourceSpan, what: &str) -> String {
+        let label = self.fresh_label("trap");
+        let message = format!(
+            "panic at line {}:{}: attempt to {what}",
+            span.line, span.col
+        );
+        self.traps.push((label.clone(), message));
+        label
+    }
+
+    fn checked(&self) -> bool {
+        self.ctx.mode == BuildMode::Debug
+    }
+
+    // Variable shifts take their count in %cl. If the value itself lives in %rcx, its saved copy
+    // on the stack is shifted instead and popped back into place
+    fn gen_shift(&mut self, instr: &str, lhsr: Register, rhsr: Register) -> Result<(), Diagnostic> {
+        if rhsr == Register::Rcx {
+            return self.emit_instr(&format!("{instr} %cl, {lhsr}"));
+        }
+
+        self.emit_instr("pushq %rcx")?;
+        self.emit_movq_reg(rhsr, Register::Rcx)?;
+        if lhsr == Register::Rcx {
+            self.emit_instr(&format!("{instr} %cl, (%rsp)"))?;
+        } else {
+            self.emit_instr(&format!("{instr} %cl, {lhsr}"))?;
+        }
+        self.emit_instr("popq %rcx")
+    }
+
+    // Zero initialized globals go in .bss, everything else is written out in .data
+    fn gen_globals(&mut self, ast: &Program) -> Result<(), Diagnostic> {
+        for stmt in &ast.top {
+            let StmtKind::VarDecl(info) = &stmt.kind else {
+                continue;
+            };
+
+            let id = info.id.unwrap();
+            let (ty, data) = {
+                let symbols = self.symbols();
+                let var_info = symbols.var_info(id);
+                let Storage::Global(data) = &var_info.storage else {
+                    unreachable!("top level variables are globals");
+                };
+                (var_info.ty.clone(), data.clone())
+            };
+            let align = self.symbols().align_of(&ty);
+            let zeroed = data.is_zeroed();
+
+            self.emit_blank()?;
+            if zeroed {
+                self.emit(".bss")?;
+            } else {
+                self.emit(".data")?;
+            }
+            self.emit_instr(&format!(".balign {align}"))?;
+            self.emit_label(&self.global_label(id))?;
+            if zeroed {
+                self.emit_instr(&format!(".zero {}", data.bytes.len().max(1)))?;
+            } else {
+                self.gen_static_data(data)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Plain bytes, except for a relocated pointer wherever the bytes of a string go
+    fn gen_static_data(&mut self, mut data: StaticData) -> Result<(), Diagnostic> {
+        data.strings.sort_by_key(|(offset, _)| *offset);
+        let mut pos = 0;
+        for (offset, contents) in data.strings {
+            self.emit_bytes(&data.bytes[pos..offset])?;
+            let label = self.fresh_label("str");
+            self.emit_instr(&format!(".quad {label}_bytes"))?;
+            self.strings.push((label, contents));
+            pos = offset + 8;
+        }
+        self.emit_bytes(&data.bytes[pos..])
+    }
+
+    fn emit_bytes(&mut self, bytes: &[u8]) -> Result<(), Diagnostic> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        let bytes: Vec<String> = bytes.iter().map(u8::to_string).collect();
+        self.emit_instr(&format!(".byte {}", bytes.join(", ")))
+    }
+
+    // Bools are always 0 or 1, so whichever side decides the result is the result
+    fn gen_short_circuit(
+        &mut self,
+        op: BinOpKind,
+        lhs: &Expr,
+        rhs: &Expr,
+    ) -> Result<Register, Diagnostic> {
+        let end_label = self.fresh_label("sc_end");
+
+        let lhsr = self.gen_expr(lhs)?;
+        self.emit_instr(&format!("testq {lhsr}, {lhsr}"))?;
+        match op {
+            BinOpKind::And => self.emit_instr(&format!("je {end_label}"))?,
+            BinOpKind::Or => self.emit_instr(&format!("jne {end_label}"))?,
+            _ => unreachable!("only logical operators short circuit"),
+        }
+
+        let rhsr = self.gen_expr(rhs)?;
+        self.emit_movq_reg(rhsr, lhsr)?;
         self.ra.free(rhsr, &mut self.out)?;
+
+        self.emit_label(&end_label)?;
         Ok(lhsr)
     }
 
+    fn fresh_label(&mut self, name: &str) -> String {
+        let label = format!(".L{name}{}", self.next_label);
+        self.next_label += 1;
+        label
+    }
+
     // TODO: Better mangling logic than whatever this is
     fn mangle(&self, id: SymbolID) -> String {
         format!("_crsnt_f{}", *id)
     }
 
+    fn global_label(&self, id: SymbolID) -> String {
+        format!("_crsnt_g{}", *id)
+    }
+
+    // Memory operand holding a variable
+    fn var_operand(&self, id: SymbolID) -> String {
+        match &self.symbols().var_info(id).storage {
+            Storage::Stack(offset) => format!("{offset}(%rbp)"),
+            Storage::Global(_) => format!("{}(%rip)", self.global_label(id)),
+            Storage::Const(_) => panic!("constants are inlined, they have no address"),
+        }
+    }
+
     fn loop_labels(&self, id: LoopID) -> (String, String) {
         (format!(".L{id}_start"), format!(".L{id}_end"))
     }
@@ -654,6 +1470,96 @@ impl<'ctx> Codegen<'ctx> {
         }
     }
 
+    // Values always sit in registers extended to the full 64 bits according to their type,
+    // so loads extend, stores only write the bytes the type occupies. Aggregates are held by
+    // address, so loading one takes its address and storing one copies it over
+    fn emit_load(&mut self, ty: &ResolvedType, src: &str, dst: Register) -> Result<(), Diagnostic> {
+        if self.symbols().is_aggregate(ty) {
+            return self.emit_instr(&format!("leaq {src}, {dst}"));
+        }
+
+        let (size, signed) = self.int_layout(ty);
+        let instr = match (size, signed) {
+            (1, true) => format!("movsbq {src}, {dst}"),
+            (1, false) => format!("movzbq {src}, {dst}"),
+            (2, true) => format!("movswq {src}, {dst}"),
+            (2, false) => format!("movzwq {src}, {dst}"),
+            (4, true) => format!("movslq {src}, {dst}"),
+            (4, false) => format!("movl {src}, {}", dst.to_32bit()),
+            _ => format!("movq {src}, {dst}"),
+        };
+        self.emit_instr(&instr)
+    }
+
+    fn emit_store(
+        &mut self,
+        ty: &ResolvedType,
+        src: Register,
+        dst: &str,
+    ) -> Result<(), Diagnostic> {
+        if self.symbols().is_aggregate(ty) {
+            let size = self.symbols().size_of(ty);
+            return self.emit_copy(size, src, dst);
+        }
+
+        let (size, _) = self.int_layout(ty);
+        let instr = match size {
+            1 => format!("movb {}, {dst}", src.to_8bit()),
+            2 => format!("movw {}, {dst}", src.to_16bit()),
+            4 => format!("movl {}, {dst}", src.to_32bit()),
+            _ => format!("movq {src}, {dst}"),
+        };
+        self.emit_instr(&instr)
+    }
+
+    // Copies `size` bytes from the address in src to dst in the widest chunks that fit. The
+    // scratch register is saved around the copy since src and dst may use any allocated register
+    fn emit_copy(&mut self, size: usize, src: Register, dst: &str) -> Result<(), Diagnostic> {
+        let scratch = [Register::Rax, Register::Rcx, Register::Rdx]
+            .into_iter()
+            .find(|&reg| reg != src && !dst.contains(&reg.to_string()))
+            .expect("an operand uses at most two registers");
+
+        self.emit_instr(&format!("pushq {scratch}"))?;
+        let mut offset = 0;
+        for chunk in [8, 4, 2, 1] {
+            while size - offset >= chunk {
+                let (instr, scratch_part) = match chunk {
+                    8 => ("movq", scratch.to_string()),
+                    4 => ("movl", scratch.to_32bit().to_string()),
+                    2 => ("movw", scratch.to_16bit().to_string()),
+                    _ => ("movb", scratch.to_8bit().to_string()),
+                };
+                let dst_chunk = offset_operand(dst, offset);
+                self.emit_instr(&format!("{instr} {offset}({src}), {scratch_part}"))?;
+                self.emit_instr(&format!("{instr} {scratch_part}, {dst_chunk}"))?;
+                offset += chunk;
+            }
+        }
+        self.emit_instr(&format!("popq {scratch}"))
+    }
+
+    // Re-extends the low bits of a register after arithmetic that may have carried past them
+    fn emit_normalize(&mut self, ty: &ResolvedType, reg: Register) -> Result<(), Diagnostic> {
+        let (size, signed) = self.int_layout(ty);
+        let instr = match (size, signed) {
+            (1, true) => format!("movsbq {}, {reg}", reg.to_8bit()),
+            (1, false) => format!("movzbq {}, {reg}", reg.to_8bit()),
+            (2, true) => format!("movswq {}, {reg}", reg.to_16bit()),
+            (2, false) => format!("movzwq {}, {reg}", reg.to_16bit()),
+            (4, true) => format!("movslq {}, {reg}", reg.to_32bit()),
+            (4, false) => format!("movl {}, {}", reg.to_32bit(), reg.to_32bit()),
+            _ => return Ok(()),
+        };
+        self.emit_instr(&instr)
+    }
+
+    fn int_layout(&self, ty: &ResolvedType) -> (usize, bool) {
+        let symbols = self.symbols();
+        let signed = symbols.is_integer(ty) && symbols.is_signed(ty);
+        (symbols.size_of(ty), signed)
+    }
+
     fn emit_movq_reg(&mut self, src: Register, dst: Register) -> Result<(), Diagnostic> {
         if src != dst {
             self.emit_instr(&format!("movq {src}, {dst}"))?;
@@ -674,10 +1580,7 @@ impl<'ctx> Codegen<'ctx> {
     }
 
     fn emit(&mut self, line: &str) -> Result<(), Diagnostic> {
-        writeln!(self.out, "{line}").map_err(|_| Diagnostic {
-            line: -1,
-            kind: DiagnosticKind::WriteErr,
-        })
+        writeln!(self.out, "{line}").map_err(|_| Diagnostic::unspanned(DiagnosticKind::WriteErr))
     }
 
     fn align_16(&self, x: usize) -> usize {
@@ -685,13 +1588,27 @@ impl<'ctx> Codegen<'ctx> {
     }
 
     fn report_write_error(&self) {
-        self.ctx.diags.borrow_mut().report(Diagnostic {
-            line: -1,
-            kind: DiagnosticKind::WriteErr,
-        });
+        self.ctx
+            .diags
+            .borrow_mut()
+            .report(Diagnostic::unspanned(DiagnosticKind::WriteErr));
     }
 
     fn symbols(&self) -> Ref<'ctx, Symbols> {
         self.ctx.symbols.borrow()
     }
 }
+
+// Memory operand `offset` bytes past `operand`, the assembler folds the displacement
+fn offset_operand(operand: &str, offset: usize) -> String {
+    match offset {
+        0 => operand.to_string(),
+        _ if operand.starts_with('(') => format!("{offset}{operand}"),
+        _ => format!("{offset}+{operand}"),
+    }
+}
+
+// Semantic analysis gives every expression a type before codegen runs
+fn expr_ty(expr: &Expr) -> &ResolvedType {
+    expr.ty.as_ref().expect("expression should be typed")
+}
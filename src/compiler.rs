@@ -4,18 +4,29 @@ use crate::semantic::SemanticAnalyzer;
 use crate::{lexer::Lexer, parser::Parser, source::Source, symbols::Symbols};
 use std::cell::RefCell;
 
+// Debug builds check arithmetic at runtime and trap on overflow or division by zero,
+// release builds emit the raw instructions and let results wrap
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BuildMode {
+    #[default]
+    Debug,
+    Release,
+}
+
 pub struct Context {
     pub source: Source,
     pub out_path: String,
+    pub mode: BuildMode,
     pub symbols: RefCell<Symbols>,
     pub diags: RefCell<Diagnostics>,
 }
 
 impl Context {
-    pub fn new(source: String, out_path: String) -> Context {
+    pub fn new(source: String, out_path: String, mode: BuildMode) -> Context {
         Context {
             source: Source::new(source),
             out_path,
+            mode,
             symbols: RefCell::new(Symbols::new()),
             diags: RefCell::new(Diagnostics::default()),
         }
@@ -27,42 +38,52 @@ pub struct Compiler {
 }
 
 impl Compiler {
-    pub fn new(source: String, out_path: String) -> Compiler {
+    pub fn new(source: String, out_path: String, mode: BuildMode) -> Compiler {
         Compiler {
-            ctx: Context::new(source, out_path),
+            ctx: Context::new(source, out_path, mode),
         }
     }
 
+    pub fn source(&self) -> &Source {
+        &self.ctx.source
+    }
+
+    // On success, returns any warnings that were reported along the way.
+    // On failure, returns every diagnostic (errors and warnings) reported so far
     // TODO: perhaps this can get less repetitive later
-    pub fn compile(&mut self) -> Result<(), Vec<Diagnostic>> {
+    pub fn compile(&mut self) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
         let mut lexer = Lexer::new(&self.ctx);
         let token_stream = lexer.tokenize();
 
-        if self.ctx.diags.borrow().has_diagnostics() {
+        if self.ctx.diags.borrow().has_errors() {
             return Err(self.ctx.diags.borrow_mut().take_diagnostics());
         }
 
         let mut parser = Parser::new(token_stream, &self.ctx);
         let mut ast = parser.parse();
 
-        if self.ctx.diags.borrow().has_diagnostics() {
+        if self.ctx.diags.borrow().has_errors() {
             return Err(self.ctx.diags.borrow_mut().take_diagnostics());
         }
 
         let mut semantics = SemanticAnalyzer::new(&self.ctx);
         semantics.analyze(&mut ast);
 
-        if self.ctx.diags.borrow().has_diagnostics() {
+        if self.ctx.diags.borrow().has_errors() {
             return Err(self.ctx.diags.borrow_mut().take_diagnostics());
         }
 
-        let mut codegen = Codegen::try_new(&self.ctx).map_err(|e| vec![e])?;
+        let mut codegen = Codegen::try_new(&self.ctx).map_err(|e| {
+            let mut diags = self.ctx.diags.borrow_mut().take_diagnostics();
+            diags.push(e);
+            diags
+        })?;
         codegen.generate_output(&ast);
 
-        if self.ctx.diags.borrow().has_diagnostics() {
+        if self.ctx.diags.borrow().has_errors() {
             return Err(self.ctx.diags.borrow_mut().take_diagnostics());
         }
 
-        Ok(())
+        Ok(self.ctx.diags.borrow_mut().take_diagnostics())
     }
 }
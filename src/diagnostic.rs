@@ -1,4 +1,6 @@
-use crate::tokens::TokenKind;
+use crate::source::Source;
+use crate::tokens::{SourceSpan, TokenKind};
+use std::collections::BTreeMap;
 use std::fmt;
 
 // Again, I'd like to avoid storing owned strings in the future
@@ -12,7 +14,7 @@ pub enum DiagnosticKind {
         found: TokenKind,
     },
     IdentRedeclared {
-        original_line: i32,
+        original: Option<Box<SourceSpan>>,
         var_name: String,
     },
     VarUnknown {
@@ -24,12 +26,22 @@ pub enum DiagnosticKind {
     FuncUnknown {
         func_name: String,
     },
-    NumLiteralTooLarge {
+    InvalidDigit {
+        digit: char,
+        radix: u32,
+    },
+    MissingDigits {
         literal: String,
     },
+    InvalidLiteralSuffix {
+        suffix: String,
+    },
     UnexpectedTokenInExpression {
         found: TokenKind,
     },
+    ChainedComparison {
+        op: TokenKind,
+    },
     ContinueOutsideLoop,
     BreakOutsideLoop,
     ReturnOutsideFunc,
@@ -44,6 +56,111 @@ pub enum DiagnosticKind {
     },
     WriteErr,
     InvalidAssignment,
+    TooManyErrors {
+        limit: usize,
+    },
+    TypeMismatch {
+        expected: String,
+        found: String,
+    },
+    IntLiteralOutOfRange {
+        literal: String,
+        ty: String,
+    },
+    InvalidCast {
+        from: String,
+        to: String,
+    },
+    InvalidCharCast {
+        from: String,
+    },
+    MissingReturn {
+        func_name: String,
+    },
+    UnreachableCode,
+    UnusedVar {
+        var_name: String,
+    },
+    UnusedParam {
+        param_name: String,
+    },
+    UnusedFunc {
+        func_name: String,
+    },
+    VoidValue,
+    InvalidArrayLen {
+        literal: String,
+    },
+    NotIndexable {
+        ty: String,
+    },
+    IndexOutOfBounds {
+        index: i64,
+        len: usize,
+    },
+    CannotInferArray,
+    NotComparable {
+        ty: String,
+    },
+    ArrayInSignature,
+    EmptyStruct {
+        struct_name: String,
+    },
+    RecursiveStruct {
+        struct_name: String,
+    },
+    VoidField {
+        field: String,
+    },
+    DuplicateField {
+        field: String,
+    },
+    NotAStruct {
+        ty: String,
+    },
+    NotAPointer {
+        ty: String,
+    },
+    NotAddressable,
+    UnterminatedString,
+    InvalidEscape {
+        escape: String,
+    },
+    StrImmutable,
+    UnterminatedChar,
+    EmptyChar,
+    MultiCharLiteral {
+        literal: String,
+    },
+    AddressOfConst {
+        const_name: String,
+    },
+    UnknownField {
+        ty: String,
+        field: String,
+    },
+    MissingFields {
+        ty: String,
+        fields: String,
+    },
+    AggregateConst {
+        const_name: String,
+        ty: String,
+    },
+    NotConstant,
+    ConstDivByZero,
+    ConstOverflow {
+        ty: String,
+    },
+    AssignToConst {
+        const_name: String,
+    },
+    UnusedConst {
+        const_name: String,
+    },
+    VoidVariable {
+        var_name: String,
+    },
 }
 
 impl fmt::Display for DiagnosticKind {
@@ -55,14 +172,8 @@ impl fmt::Display for DiagnosticKind {
             Self::UnexpectedToken { expected, found } => {
                 write!(f, "Expected token '{expected}', found '{found}'")
             }
-            Self::IdentRedeclared {
-                original_line,
-                var_name,
-            } => {
-                write!(
-                    f,
-                    "Identifier '{var_name}' redeclared. (Orignally declared on line {original_line})"
-                )
+            Self::IdentRedeclared { var_name, .. } => {
+                write!(f, "Identifier '{var_name}' redeclared")
             }
             Self::VarUnknown { var_name } => {
                 write!(f, "Unknown variable '{var_name}'")
@@ -73,12 +184,21 @@ impl fmt::Display for DiagnosticKind {
             Self::TypeUnknown { type_name } => {
                 write!(f, "Unknown type '{type_name}'")
             }
-            Self::NumLiteralTooLarge { literal } => {
-                write!(f, "Number literal {literal} too large")
+            Self::InvalidDigit { digit, radix } => {
+                write!(f, "Invalid digit '{digit}' in a base {radix} literal")
+            }
+            Self::MissingDigits { literal } => {
+                write!(f, "Literal {literal} has no digits")
+            }
+            Self::InvalidLiteralSuffix { suffix } => {
+                write!(f, "Invalid suffix '{suffix}' for an integer literal")
             }
             Self::UnexpectedTokenInExpression { found } => {
                 write!(f, "Unexpected token '{found}' found within expression")
             }
+            Self::ChainedComparison { op } => {
+                write!(f, "Comparison operator '{op}' can't be chained")
+            }
             Self::ContinueOutsideLoop => {
                 write!(f, "'continue' statement oustide of loop")
             }
@@ -91,7 +211,7 @@ impl fmt::Display for DiagnosticKind {
             Self::InvalidMain => {
                 write!(
                     f,
-                    "Could not find main function with signature\n\n\tfunc main(): i32\n\nin global scope"
+                    "Could not find main function with signature\n\n\tfunc main(): i64\n\nin global scope"
                 )
             }
             Self::FailedOutOpen { path } => {
@@ -118,25 +238,286 @@ impl fmt::Display for DiagnosticKind {
             Self::WriteErr => {
                 write!(f, "Error writing to file")
             }
+            Self::TypeMismatch { expected, found } => {
+                write!(
+                    f,
+                    "Mismatched types, expected '{expected}', found '{found}'"
+                )
+            }
+            Self::IntLiteralOutOfRange { literal, ty } => {
+                write!(f, "Literal {literal} does not fit in type '{ty}'")
+            }
+            Self::InvalidCast { from, to } => {
+                write!(f, "Cannot cast '{from}' to '{to}'")
+            }
+            Self::InvalidCharCast { from } => {
+                write!(f, "Only 'u8' can be cast to 'char', not '{from}'")
+            }
+            Self::MissingReturn { func_name } => {
+                write!(
+                    f,
+                    "Function '{func_name}' can reach the end of its body without returning a value"
+                )
+            }
+            Self::UnreachableCode => {
+                write!(f, "Unreachable statement")
+            }
+            Self::UnusedVar { var_name } => {
+                write!(f, "Variable '{var_name}' is never used")
+            }
+            Self::UnusedParam { param_name } => {
+                write!(f, "Parameter '{param_name}' is never used")
+            }
+            Self::UnusedFunc { func_name } => {
+                write!(f, "Function '{func_name}' is never used")
+            }
+            Self::NotConstant => {
+                write!(f, "Expression can't be evaluated at compile time")
+            }
+            Self::ConstDivByZero => {
+                write!(f, "Division by zero in constant expression")
+            }
+            Self::ConstOverflow { ty } => {
+                write!(f, "Constant expression overflows type '{ty}'")
+            }
+            Self::AssignToConst { const_name } => {
+                write!(f, "Cannot assign to constant '{const_name}'")
+            }
+            Self::UnusedConst { const_name } => {
+                write!(f, "Constant '{const_name}' is never used")
+            }
+            Self::InvalidArrayLen { literal } => {
+                write!(f, "Invalid array length {literal}")
+            }
+            Self::NotIndexable { ty } => {
+                write!(f, "Cannot index into a value of type '{ty}'")
+            }
+            Self::IndexOutOfBounds { index, len } => {
+                write!(
+                    f,
+                    "Index {index} is out of bounds for an array of length {len}"
+                )
+            }
+            Self::CannotInferArray => {
+                write!(f, "Cannot infer the element type of an empty array")
+            }
+            Self::NotComparable { ty } => {
+                write!(f, "Values of type '{ty}' can't be compared")
+            }
+            Self::ArrayInSignature => {
+                write!(f, "Arrays can't be passed to or returned from functions")
+            }
+            Self::EmptyStruct { struct_name } => {
+                write!(f, "Struct '{struct_name}' has no fields")
+            }
+            Self::RecursiveStruct { struct_name } => {
+                write!(
+                    f,
+                    "Struct '{struct_name}' contains itself and would have infinite size"
+                )
+            }
+            Self::VoidField { field } => {
+                write!(f, "Field '{field}' can't have type 'void'")
+            }
+            Self::DuplicateField { field } => {
+                write!(f, "Field '{field}' is given more than once")
+            }
+            Self::NotAStruct { ty } => {
+                write!(f, "Type '{ty}' is not a struct")
+            }
+            Self::NotAPointer { ty } => {
+                write!(f, "Type '{ty}' can't be dereferenced, it is not a pointer")
+            }
+            Self::NotAddressable => {
+                write!(
+                    f,
+                    "Only variables, elements, fields and dereferences have an address"
+                )
+            }
+            Self::UnterminatedString => {
+                write!(f, "Unterminated string literal")
+            }
+            Self::InvalidEscape { escape } => {
+                write!(f, "Invalid escape sequence '{escape}'")
+            }
+            Self::UnterminatedChar => {
+                write!(f, "Unterminated character literal")
+            }
+            Self::EmptyChar => {
+                write!(f, "Empty character literal")
+            }
+            Self::MultiCharLiteral { literal } => {
+                write!(
+                    f,
+                    "Character literal {literal} holds more than one character"
+                )
+            }
+            Self::StrImmutable => {
+                write!(f, "Strings can't be modified in place")
+            }
+            Self::AddressOfConst { const_name } => {
+                write!(
+                    f,
+                    "Constant '{const_name}' has no address, it is inlined where used"
+                )
+            }
+            Self::UnknownField { ty, field } => {
+                write!(f, "Struct '{ty}' has no field '{field}'")
+            }
+            Self::MissingFields { ty, fields } => {
+                write!(f, "Missing fields {fields} in struct '{ty}'")
+            }
+            Self::AggregateConst { const_name, ty } => {
+                write!(f, "Constant '{const_name}' can't have type '{ty}'")
+            }
+            Self::VoidValue => {
+                write!(f, "Expression of type 'void' used as a value")
+            }
+            Self::VoidVariable { var_name } => {
+                write!(f, "Variable '{var_name}' can't have type 'void'")
+            }
+            Self::TooManyErrors { limit } => {
+                write!(f, "Aborting after {limit} errors")
+            }
+        }
+    }
+}
+
+impl DiagnosticKind {
+    // Extra source locations worth pointing at besides the primary span
+    pub fn labels(&self) -> Vec<Label> {
+        match self {
+            Self::IdentRedeclared {
+                original: Some(original),
+                var_name,
+            } => vec![Label {
+                span: **original,
+                message: format!("'{var_name}' originally declared here"),
+            }],
+            _ => vec![],
         }
     }
 }
 
-//TODO: We use -1 for no line, maybe change to option?
+#[derive(Debug)]
+pub struct Label {
+    pub span: SourceSpan,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rep = match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+            Severity::Help => "help",
+        };
+        write!(f, "{rep}")
+    }
+}
+
+// A note or help message attached to a diagnostic, optionally pointing somewhere else in the source
+#[derive(Debug)]
+pub struct SubDiagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<SourceSpan>,
+}
+
+// Diagnostics without a span (e.g. IO errors) are not tied to any source location. Every
+// Result carries one, so the rarely used parts (sub-notes, a redeclaration's original span) are
+// boxed to keep it small
 #[derive(Debug)]
 pub struct Diagnostic {
-    pub line: i32,
+    pub severity: Severity,
+    pub span: Option<SourceSpan>,
     pub kind: DiagnosticKind,
+    pub children: Box<[SubDiagnostic]>,
+}
+
+impl Diagnostic {
+    pub fn error(kind: DiagnosticKind, span: SourceSpan) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            span: Some(span),
+            kind,
+            children: Box::default(),
+        }
+    }
+
+    pub fn warning(kind: DiagnosticKind, span: SourceSpan) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            span: Some(span),
+            kind,
+            children: Box::default(),
+        }
+    }
+
+    pub fn unspanned(kind: DiagnosticKind) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            span: None,
+            kind,
+            children: Box::default(),
+        }
+    }
+
+    pub fn with_note(self, message: impl Into<String>) -> Self {
+        self.with_child(Severity::Note, message, None)
+    }
+
+    pub fn with_help(self, message: impl Into<String>) -> Self {
+        self.with_child(Severity::Help, message, None)
+    }
+
+    pub fn with_spanned_note(self, message: impl Into<String>, span: SourceSpan) -> Self {
+        self.with_child(Severity::Note, message, Some(span))
+    }
+
+    fn with_child(
+        mut self,
+        severity: Severity,
+        message: impl Into<String>,
+        span: Option<SourceSpan>,
+    ) -> Self {
+        let mut children = std::mem::take(&mut self.children).into_vec();
+        children.push(SubDiagnostic {
+            severity,
+            message: message.into(),
+            span,
+        });
+        self.children = children.into_boxed_slice();
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
 }
 
 impl fmt::Display for Diagnostic {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.line > 0 {
-            write!(f, "ERROR (line {}): ", self.line)?;
+        let severity = self.severity.to_string().to_uppercase();
+        if let Some(span) = self.span {
+            write!(f, "{severity} (line {}, column {}): ", span.line, span.col)?;
         } else {
-            write!(f, "ERROR: ")?
+            write!(f, "{severity}: ")?
         }
-        write!(f, "{}", self.kind)
+        write!(f, "{}", self.kind)?;
+        for child in &self.children {
+            write!(f, "\n\t{}: {}", child.severity, child.message)?;
+        }
+        Ok(())
     }
 }
 
@@ -150,11 +531,156 @@ impl Diagnostics {
         self.diagnostics.push(diagnostic);
     }
 
-    pub fn has_diagnostics(&self) -> bool {
-        self.diagnostics.len() > 0
+    // Only errors stop the pipeline, warnings and notes are just reported
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|diag| diag.is_error())
+            .count()
     }
 
     pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
         std::mem::take(&mut self.diagnostics)
     }
 }
+
+// One underline to draw beneath a source line
+struct Underline<'a> {
+    col: usize,
+    width: usize,
+    marker: char,
+    message: Option<&'a str>,
+}
+
+// Renders diagnostics rustc-style, with the offending source lines and carets
+// underneath the spans they point at
+pub struct DiagnosticRenderer<'a> {
+    source: &'a Source,
+    path: &'a str,
+}
+
+impl<'a> DiagnosticRenderer<'a> {
+    pub fn new(source: &'a Source, path: &'a str) -> Self {
+        DiagnosticRenderer { source, path }
+    }
+
+    pub fn render(&self, diag: &Diagnostic) -> String {
+        let secondary = diag.kind.labels();
+        let mut out =
+            self.render_block(diag.severity, &diag.kind.to_string(), diag.span, &secondary);
+
+        for child in &diag.children {
+            match child.span {
+                Some(_) => {
+                    out += &self.render_block(child.severity, &child.message, child.span, &[])
+                }
+                None => {
+                    let pad = " ".repeat(self.gutter_width(diag.span, &secondary));
+                    out += &format!("{pad} = {}: {}\n", child.severity, child.message);
+                }
+            }
+        }
+
+        out
+    }
+
+    fn render_block(
+        &self,
+        severity: Severity,
+        message: &str,
+        span: Option<SourceSpan>,
+        secondary: &[Label],
+    ) -> String {
+        let mut out = format!("{severity}: {message}\n");
+        let Some(span) = span else {
+            return out;
+        };
+
+        let mut lines: BTreeMap<usize, Vec<Underline>> = BTreeMap::new();
+        let marker = if severity == Severity::Error || severity == Severity::Warning {
+            '^'
+        } else {
+            '-'
+        };
+        self.add_underlines(&mut lines, span, marker, None);
+        for label in secondary {
+            self.add_underlines(&mut lines, label.span, '-', Some(&label.message));
+        }
+
+        let gutter = self.gutter_width(Some(span), secondary);
+        let pad = " ".repeat(gutter);
+
+        out += &format!("{pad}--> {}:{}:{}\n", self.path, span.line, span.col);
+        out += &format!("{pad} |\n");
+
+        let mut prev_line: Option<usize> = None;
+        for (line, underlines) in &lines {
+            if let Some(prev) = prev_line
+                && line - prev > 1
+            {
+                out += "...\n";
+            }
+            prev_line = Some(*line);
+
+            let text = self.source.line_text(*line);
+            out += &format!("{line:>gutter$} | {text}\n");
+
+            for underline in underlines {
+                // Reuse the source's own tabs so the markers line up however tabs are displayed
+                let indent: String = text
+                    .chars()
+                    .take(underline.col - 1)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                let markers = underline.marker.to_string().repeat(underline.width);
+                out += &format!("{pad} | {indent}{markers}");
+                if let Some(message) = underline.message {
+                    out += &format!(" {message}");
+                }
+                out += "\n";
+            }
+        }
+
+        out
+    }
+
+    // Width of the line number gutter, wide enough for the last line any span touches
+    fn gutter_width(&self, span: Option<SourceSpan>, secondary: &[Label]) -> usize {
+        span.iter()
+            .chain(secondary.iter().map(|label| &label.span))
+            .map(|span| self.source.line_col(span.high).0.to_string().len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn add_underlines<'m>(
+        &self,
+        lines: &mut BTreeMap<usize, Vec<Underline<'m>>>,
+        span: SourceSpan,
+        marker: char,
+        message: Option<&'m str>,
+    ) {
+        let (first_line, first_col) = self.source.line_col(span.low);
+        let (last_line, last_col) = self.source.line_col(span.high);
+
+        for line in first_line..=last_line {
+            let start = if line == first_line { first_col } else { 1 };
+            let end = if line == last_line {
+                last_col
+            } else {
+                self.source.line_text(line).chars().count() + 1
+            };
+
+            lines.entry(line).or_default().push(Underline {
+                col: start,
+                width: end.saturating_sub(start).max(1),
+                marker,
+                message: if line == last_line { message } else { None },
+            });
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::compiler::Context;
 use crate::diagnostic::{Diagnostic, DiagnosticKind};
-use crate::tokens::{Token, TokenKind, TokenStream};
+use crate::tokens::{SourceSpan, Token, TokenKind, TokenStream};
 
 fn is_identifier_char(c: char) -> bool {
     c.is_alphanumeric() || c == '_'
@@ -14,8 +14,14 @@ fn get_keyword(identifier: &str) -> Option<TokenKind> {
         "else" => Some(TokenKind::Else),
         "while" => Some(TokenKind::While),
         "let" => Some(TokenKind::Let),
+        "const" => Some(TokenKind::Const),
+        "struct" => Some(TokenKind::Struct),
+        "str" => Some(TokenKind::Str),
         "continue" => Some(TokenKind::Continue),
         "break" => Some(TokenKind::Break),
+        "true" => Some(TokenKind::True),
+        "false" => Some(TokenKind::False),
+        "as" => Some(TokenKind::As),
         _ => None,
     }
 }
@@ -25,6 +31,9 @@ pub struct Lexer<'ctx> {
     start: usize,
     position: usize,
     line: i32,
+    line_start: usize,
+    start_line: i32,
+    start_col: usize,
 }
 
 // TODO: Intern strings on lexing
@@ -35,6 +44,9 @@ impl<'ctx> Lexer<'ctx> {
             start: 0,
             position: 0,
             line: 1,
+            line_start: 0,
+            start_line: 1,
+            start_col: 1,
         }
     }
 
@@ -42,15 +54,12 @@ impl<'ctx> Lexer<'ctx> {
         let mut tokens: Vec<Token> = vec![];
 
         loop {
-            match self.next_token() {
-                Some(token) => {
-                    let is_eof = token.kind == TokenKind::EOF;
-                    tokens.push(token);
-                    if is_eof {
-                        break;
-                    }
+            if let Some(token) = self.next_token() {
+                let is_eof = token.kind == TokenKind::EOF;
+                tokens.push(token);
+                if is_eof {
+                    break;
                 }
-                None => {}
             };
         }
 
@@ -60,13 +69,11 @@ impl<'ctx> Lexer<'ctx> {
     fn next_token(&mut self) -> Option<Token> {
         self.skip_whitespace();
         self.start = self.position;
+        self.start_line = self.line;
+        self.start_col = self.ctx.source[self.line_start..self.start].chars().count() + 1;
 
         let token = match self.advance_char() {
-            None => Token {
-                kind: TokenKind::EOF,
-                lexeme: "".to_string(),
-                line: self.line,
-            },
+            None => self.make_token(TokenKind::EOF),
             Some(c) => match c {
                 ';' => self.make_token(TokenKind::Semi),
                 ':' => self.make_token(TokenKind::Colon),
@@ -74,7 +81,12 @@ impl<'ctx> Lexer<'ctx> {
                 '}' => self.make_token(TokenKind::CloseCurly),
                 '(' => self.make_token(TokenKind::OpenParen),
                 ')' => self.make_token(TokenKind::CloseParen),
+                '[' => self.make_token(TokenKind::OpenBracket),
+                ']' => self.make_token(TokenKind::CloseBracket),
                 ',' => self.make_token(TokenKind::Comma),
+                '.' => self.make_token(TokenKind::Dot),
+                '"' => self.lex_string(),
+                '\'' => self.lex_char(),
                 '!' => {
                     let kind = self.match_switch('=', TokenKind::BangEq, TokenKind::Bang);
                     self.make_token(kind)
@@ -83,25 +95,58 @@ impl<'ctx> Lexer<'ctx> {
                     let kind = self.match_switch('=', TokenKind::EqEq, TokenKind::Eq);
                     self.make_token(kind)
                 }
+                '<' if self.match_char('<') => {
+                    let kind = self.match_switch('=', TokenKind::LessLessEq, TokenKind::LessLess);
+                    self.make_token(kind)
+                }
                 '<' => {
                     let kind = self.match_switch('=', TokenKind::LessEq, TokenKind::LessThan);
                     self.make_token(kind)
                 }
+                '>' if self.match_char('>') => {
+                    let kind = self.match_switch(
+                        '=',
+                        TokenKind::GreaterGreaterEq,
+                        TokenKind::GreaterGreater,
+                    );
+                    self.make_token(kind)
+                }
                 '>' => {
                     let kind = self.match_switch('=', TokenKind::GreaterEq, TokenKind::GreaterThan);
                     self.make_token(kind)
                 }
-                '+' => self.make_token(TokenKind::Plus),
+                '&' if self.match_char('&') => self.make_token(TokenKind::AmpAmp),
+                '&' => {
+                    let kind = self.match_switch('=', TokenKind::AmpEq, TokenKind::Amp);
+                    self.make_token(kind)
+                }
+                '|' if self.match_char('|') => self.make_token(TokenKind::PipePipe),
+                '|' => {
+                    let kind = self.match_switch('=', TokenKind::PipeEq, TokenKind::Pipe);
+                    self.make_token(kind)
+                }
+                '^' => {
+                    let kind = self.match_switch('=', TokenKind::CaretEq, TokenKind::Caret);
+                    self.make_token(kind)
+                }
+                '~' => self.make_token(TokenKind::Tilde),
+                '%' => {
+                    let kind = self.match_switch('=', TokenKind::PercentEq, TokenKind::Percent);
+                    self.make_token(kind)
+                }
+                '+' => {
+                    let kind = self.match_switch('=', TokenKind::PlusEq, TokenKind::Plus);
+                    self.make_token(kind)
+                }
+                // Always an operator, negative literals are folded by the parser
                 '-' => {
-                    if let Some(c) = self.peek_char()
-                        && c.is_numeric()
-                    {
-                        self.lex_literal()
-                    } else {
-                        self.make_token(TokenKind::Minus)
-                    }
+                    let kind = self.match_switch('=', TokenKind::MinusEq, TokenKind::Minus);
+                    self.make_token(kind)
+                }
+                '*' => {
+                    let kind = self.match_switch('=', TokenKind::StarEq, TokenKind::Star);
+                    self.make_token(kind)
                 }
-                '*' => self.make_token(TokenKind::Star),
                 '/' => {
                     if let Some(c) = self.peek_char()
                         && c == '/'
@@ -109,19 +154,20 @@ impl<'ctx> Lexer<'ctx> {
                         self.skip_comment();
                         return None;
                     } else {
-                        self.make_token(TokenKind::Slash)
+                        let kind = self.match_switch('=', TokenKind::SlashEq, TokenKind::Slash);
+                        self.make_token(kind)
                     }
                 }
 
                 x if x.is_alphabetic() || x == '_' => self.lex_identifier(),
-                x if x.is_numeric() => self.lex_literal(),
+                x if x.is_ascii_digit() => self.lex_literal(),
                 _ => {
-                    self.ctx.diags.borrow_mut().report(Diagnostic {
-                        line: self.line,
-                        kind: DiagnosticKind::InvalidToken {
+                    self.report(Diagnostic::error(
+                        DiagnosticKind::InvalidToken {
                             lexeme: c.to_string(),
                         },
-                    });
+                        self.current_span(),
+                    ));
                     return None;
                 }
             },
@@ -147,15 +193,16 @@ impl<'ctx> Lexer<'ctx> {
     }
 
     fn peek_char(&self) -> Option<char> {
-        return self.ctx.source[self.position..].chars().next();
+        self.ctx.source[self.position..].chars().next()
     }
 
     fn advance_char(&mut self) -> Option<char> {
         let c = self.ctx.source[self.position..].chars().next()?;
+        self.position += c.len_utf8();
         if c == '\n' {
             self.line += 1;
+            self.line_start = self.position;
         }
-        self.position += c.len_utf8();
         Some(c)
     }
 
@@ -189,9 +236,10 @@ impl<'ctx> Lexer<'ctx> {
         self.make_token(token_kind)
     }
 
+    // Takes in the radix prefix, separators and type suffix too, the parser makes sense of them
     fn lex_literal(&mut self) -> Token {
         while let Some(c) = self.peek_char()
-            && c.is_numeric()
+            && (c.is_ascii_alphanumeric() || c == '_')
         {
             self.advance_char();
         }
@@ -199,19 +247,152 @@ impl<'ctx> Lexer<'ctx> {
         self.make_token(TokenKind::Literal)
     }
 
+    // An unterminated string still makes a token, so parsing can carry on
+    fn lex_string(&mut self) -> Token {
+        let mut contents = String::new();
+        loop {
+            match self.advance_char() {
+                Some('"') => break,
+                Some('\\') => contents.extend(self.lex_escape()),
+                Some(c) => contents.push(c),
+                None => {
+                    self.report(Diagnostic::error(
+                        DiagnosticKind::UnterminatedString,
+                        self.current_span(),
+                    ));
+                    break;
+                }
+            }
+        }
+
+        Token {
+            kind: TokenKind::StrLiteral,
+            lexeme: contents,
+            span: self.current_span(),
+        }
+    }
+
+    // Literals that are empty, unterminated or hold more than one character are reported,
+    // the token keeps the first character read so parsing can carry on
+    fn lex_char(&mut self) -> Token {
+        let mut chars = vec![];
+        let mut terminated = false;
+        while let Some(c) = self.peek_char()
+            && c != '\n'
+        {
+            self.advance_char();
+            match c {
+                '\'' => {
+                    terminated = true;
+                    break;
+                }
+                '\\' => chars.extend(self.lex_escape()),
+                c => chars.push(c),
+            }
+        }
+
+        let error = if !terminated {
+            Some(DiagnosticKind::UnterminatedChar)
+        } else if self.current_lexeme() == "''" {
+            Some(DiagnosticKind::EmptyChar)
+        } else if chars.len() > 1 {
+            Some(DiagnosticKind::MultiCharLiteral {
+                literal: self.current_lexeme().to_string(),
+            })
+        } else {
+            None
+        };
+        if let Some(kind) = error {
+            self.report(Diagnostic::error(kind, self.current_span()));
+        }
+
+        Token {
+            kind: TokenKind::CharLiteral,
+            lexeme: chars.first().copied().unwrap_or('\0').to_string(),
+            span: self.current_span(),
+        }
+    }
+
+    // Reads the rest of an escape sequence after its backslash. Bytes above 0x7f can only be
+    // written as `\u{...}`, so the contents always stay valid UTF-8
+    fn lex_escape(&mut self) -> Option<char> {
+        let low = self.position - 1;
+        let line = self.line;
+        let col = self.ctx.source[self.line_start..low].chars().count() + 1;
+
+        let escaped = match self.advance_char() {
+            Some('n') => Some('\n'),
+            Some('t') => Some('\t'),
+            Some('r') => Some('\r'),
+            Some('0') => Some('\0'),
+            Some(c @ ('\\' | '"' | '\'')) => Some(c),
+            Some('x') => self
+                .lex_hex_digits(2)
+                .filter(|&value| value <= 0x7f)
+                .and_then(char::from_u32),
+            Some('u') if self.match_char('{') => {
+                let value = self.lex_hex_digits(6);
+                if self.match_char('}') {
+                    value.and_then(char::from_u32)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        if escaped.is_none() {
+            let high = self.position;
+            self.report(Diagnostic::error(
+                DiagnosticKind::InvalidEscape {
+                    escape: self.ctx.source[low..high].to_string(),
+                },
+                SourceSpan {
+                    low,
+                    high,
+                    line,
+                    col,
+                },
+            ));
+        }
+        escaped
+    }
+
+    // Between one and `max` hex digits, None if there are none
+    fn lex_hex_digits(&mut self, max: usize) -> Option<u32> {
+        let mut value = None;
+        for _ in 0..max {
+            let Some(digit) = self.peek_char().and_then(|c| c.to_digit(16)) else {
+                break;
+            };
+            self.advance_char();
+            value = Some(value.unwrap_or(0) * 16 + digit);
+        }
+        value
+    }
+
+    fn report(&self, diag: Diagnostic) {
+        self.ctx.diags.borrow_mut().report(diag);
+    }
+
     fn current_lexeme(&self) -> &'ctx str {
         &self.ctx.source[self.start..self.position]
     }
 
+    fn current_span(&self) -> SourceSpan {
+        SourceSpan {
+            low: self.start,
+            high: self.position,
+            line: self.start_line,
+            col: self.start_col,
+        }
+    }
+
     fn make_token(&self, kind: TokenKind) -> Token {
         Token {
             kind,
             lexeme: self.current_lexeme().to_string(),
-            // span: SourceSpan {
-            //     low: self.start,
-            //     high: self.position
-            // },
-            line: self.line,
+            span: self.current_span(),
         }
     }
 }
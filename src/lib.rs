@@ -1,3 +1,4 @@
+pub mod abi;
 pub mod ast;
 pub mod codegen;
 pub mod compiler;
@@ -9,4 +10,4 @@ pub mod source;
 pub mod symbols;
 pub mod tokens;
 
-pub use compiler::Compiler;
+pub use compiler::{BuildMode, Compiler};
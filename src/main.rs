@@ -1,27 +1,49 @@
-use crescent_lang::Compiler;
+use crescent_lang::diagnostic::DiagnosticRenderer;
+use crescent_lang::{BuildMode, Compiler};
 use std::fs;
 use std::process::exit;
 
 fn main() {
-    let filename = std::env::args().nth(1).unwrap_or_else(|| {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    // `--release` turns off the runtime arithmetic checks, it can go anywhere on the line
+    let mode = match args.iter().position(|arg| arg == "--release") {
+        Some(pos) => {
+            args.remove(pos);
+            BuildMode::Release
+        }
+        None => BuildMode::Debug,
+    };
+
+    let mut args = args.into_iter();
+    let filename = args.next().unwrap_or_else(|| {
         eprintln!("Invalid Arguments!");
-        eprintln!("Expected Usage: lang {{filename}} {{out_file [defaults to out.crsnt]}}");
+        eprintln!("Expected Usage: lang {{filename}} {{out_file [defaults to out.s]}} [--release]");
         exit(1)
     });
 
-    let out_path = std::env::args().nth(2).unwrap_or("out.s".to_string());
+    let out_path = args.next().unwrap_or("out.s".to_string());
 
     let source = fs::read_to_string(&filename).unwrap_or_else(|_| {
         eprintln!("ERROR: Failed to read file '{}'", &filename);
         exit(1);
     });
 
-    let mut compiler = Compiler::new(source, out_path);
+    let mut compiler = Compiler::new(source, out_path, mode);
 
-    if let Err(errors) = compiler.compile() {
-        for e in errors {
-            eprintln!("{e}");
+    let result = compiler.compile();
+    let renderer = DiagnosticRenderer::new(compiler.source(), &filename);
+    match result {
+        Ok(warnings) => {
+            for w in warnings {
+                eprintln!("{}", renderer.render(&w));
+            }
+        }
+        Err(diagnostics) => {
+            for d in diagnostics {
+                eprintln!("{}", renderer.render(&d));
+            }
+            exit(1);
         }
-        exit(1);
     }
 }
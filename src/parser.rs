@@ -1,8 +1,9 @@
-use crate::ast::{BinOpKind, Expr, Program, Stmt, StmtKind, UnOpKind};
+use crate::ast::{BinOpKind, Expr, ExprKind, LiteralInfo, Program, Stmt, StmtKind, UnOpKind};
 use crate::compiler::Context;
 use crate::diagnostic::{Diagnostic, DiagnosticKind};
+use crate::source::Source;
 use crate::symbols::GenericType;
-use crate::tokens::{Token, TokenKind, TokenStream};
+use crate::tokens::{SourceSpan, Token, TokenKind, TokenStream};
 
 pub type ParsedType = GenericType<Token>;
 
@@ -12,9 +13,22 @@ pub struct ParsedParam {
     pub ty: ParsedType,
 }
 
+// Struct fields are written exactly like parameters, `name: Type`
+pub type ParsedField = ParsedParam;
+
+// Integer types a literal can be suffixed with
+const INT_SUFFIXES: [&str; 8] = ["i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64"];
+
+// Loosest binding level in the operator table, where a full expression starts
+const MAX_PRECEDENCE: u32 = 11;
+
+// Past this many syntax errors the rest are most likely cascades of earlier ones
+const MAX_PARSE_ERRORS: usize = 20;
+
 pub struct Parser<'ctx> {
     ctx: &'ctx Context,
     token_stream: TokenStream,
+    error_count: usize,
 }
 
 impl<'ctx> Parser<'ctx> {
@@ -22,35 +36,117 @@ impl<'ctx> Parser<'ctx> {
         Parser {
             ctx: context,
             token_stream,
+            error_count: 0,
         }
     }
 
-    // TODO: In the future, this will synchronize to report multiple errors
     pub fn parse(&mut self) -> Program {
         let mut statements = vec![];
-        while self.token_stream.any() {
-            match self.parse_func() {
+        while self.token_stream.any() && !self.gave_up() {
+            match self.parse_top_level() {
                 Ok(stmt) => statements.push(stmt),
                 Err(diagnostic) => {
-                    self.ctx.diags.borrow_mut().report(diagnostic);
-                    break;
+                    self.report(diagnostic);
+                    self.synchronize_top_level();
                 }
             }
         }
         Program { top: statements }
     }
 
+    fn report(&mut self, diagnostic: Diagnostic) {
+        self.error_count += 1;
+        if self.error_count <= MAX_PARSE_ERRORS {
+            self.ctx.diags.borrow_mut().report(diagnostic);
+        } else if self.error_count == MAX_PARSE_ERRORS + 1 {
+            self.ctx.diags.borrow_mut().report(Diagnostic::unspanned(
+                DiagnosticKind::TooManyErrors {
+                    limit: MAX_PARSE_ERRORS,
+                },
+            ));
+        }
+    }
+
+    fn gave_up(&self) -> bool {
+        self.error_count > MAX_PARSE_ERRORS
+    }
+
+    // Panic mode recovery: skip past the end of the broken statement, or up to
+    // the start of the next one, so parsing can carry on
+    fn synchronize(&mut self) {
+        while self.token_stream.any() {
+            match self.token_stream.peek().kind {
+                TokenKind::Semi => {
+                    self.token_stream.advance();
+                    return;
+                }
+                // Skip whole nested blocks so their '}' isn't mistaken for ours
+                TokenKind::OpenCurly => self.skip_nested_block(),
+                TokenKind::CloseCurly
+                | TokenKind::Func
+                | TokenKind::Let
+                | TokenKind::Const
+                | TokenKind::If
+                | TokenKind::While
+                | TokenKind::Return => return,
+                _ => {
+                    self.token_stream.advance();
+                }
+            }
+        }
+    }
+
+    fn skip_nested_block(&mut self) {
+        let mut depth = 0;
+        while self.token_stream.any() {
+            match self.token_stream.advance().kind {
+                TokenKind::OpenCurly => depth += 1,
+                TokenKind::CloseCurly => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                return;
+            }
+        }
+    }
+
+    fn synchronize_top_level(&mut self) {
+        while self.token_stream.any() {
+            match self.token_stream.peek().kind {
+                TokenKind::Func | TokenKind::Let | TokenKind::Const | TokenKind::Struct => return,
+                // Declarations inside a skipped body aren't top level
+                TokenKind::OpenCurly => self.skip_nested_block(),
+                _ => {
+                    self.token_stream.advance();
+                }
+            }
+        }
+    }
+
+    fn parse_top_level(&mut self) -> Result<Stmt, Diagnostic> {
+        let statement = match self.token_stream.peek().kind {
+            TokenKind::Let => self.parse_let()?,
+            TokenKind::Const => self.parse_const()?,
+            TokenKind::Struct => return self.parse_struct(),
+            _ => return self.parse_func(),
+        };
+
+        self.token_stream.expect(TokenKind::Semi)?;
+        Ok(statement)
+    }
+
     fn parse_statement(&mut self) -> Result<Stmt, Diagnostic> {
         let tok = self.token_stream.peek();
 
         let statement = match tok.kind {
             // Tokens without semicolons
-            TokenKind::OpenCurly => return Ok(self.parse_block()?),
-            TokenKind::If => return Ok(self.parse_if()?),
-            TokenKind::While => return Ok(self.parse_while()?),
+            TokenKind::OpenCurly => return self.parse_block(),
+            TokenKind::If => return self.parse_if(),
+            TokenKind::While => return self.parse_while(),
 
             // Tokens with semicolons
             TokenKind::Let => self.parse_let()?,
+            TokenKind::Const => self.parse_const()?,
             TokenKind::Return => self.parse_return()?,
             TokenKind::Continue => self.parse_continue()?,
             TokenKind::Break => self.parse_break()?,
@@ -58,10 +154,7 @@ impl<'ctx> Parser<'ctx> {
 
             // TODO: Allow this later
             TokenKind::Func => {
-                return Err(Diagnostic {
-                    line: tok.line,
-                    kind: DiagnosticKind::FuncInScope,
-                });
+                return Err(Diagnostic::error(DiagnosticKind::FuncInScope, tok.span));
             }
 
             // TODO: Refactor to only allow starting expr tokens to avoid _
@@ -75,12 +168,27 @@ impl<'ctx> Parser<'ctx> {
     fn parse_block(&mut self) -> Result<Stmt, Diagnostic> {
         let token = self.token_stream.expect(TokenKind::OpenCurly)?;
         let mut statements = vec![];
-        while self.token_stream.any() && self.token_stream.peek().kind != TokenKind::CloseCurly {
-            statements.push(self.parse_statement()?);
+        while self.token_stream.any()
+            && self.token_stream.peek().kind != TokenKind::CloseCurly
+            && !self.gave_up()
+        {
+            match self.parse_statement() {
+                Ok(stmt) => statements.push(stmt),
+                Err(diagnostic) => {
+                    self.report(diagnostic);
+                    self.synchronize();
+
+                    // Most likely a missing '}', leave it to the closing expect below
+                    if self.token_stream.peek().kind == TokenKind::Func {
+                        break;
+                    }
+                }
+            }
         }
         self.token_stream.expect(TokenKind::CloseCurly)?;
 
-        Ok(Stmt::block(statements, token))
+        let span = self.span_from(token.span);
+        Ok(Stmt::block(statements, token, span))
     }
 
     fn parse_if(&mut self) -> Result<Stmt, Diagnostic> {
@@ -94,7 +202,8 @@ impl<'ctx> Parser<'ctx> {
             None
         };
 
-        Ok(Stmt::if_else(cond, do_if, do_else, token))
+        let span = self.span_from(token.span);
+        Ok(Stmt::if_else(cond, do_if, do_else, token, span))
     }
 
     fn parse_while(&mut self) -> Result<Stmt, Diagnostic> {
@@ -102,26 +211,44 @@ impl<'ctx> Parser<'ctx> {
         let cond = self.parse_expr()?;
         let statement = self.parse_statement()?;
 
-        Ok(Stmt::while_loop(cond, statement, token))
+        let span = self.span_from(token.span);
+        Ok(Stmt::while_loop(cond, statement, token, span))
     }
 
     fn parse_let(&mut self) -> Result<Stmt, Diagnostic> {
-        self.token_stream.expect(TokenKind::Let)?;
+        let let_token = self.token_stream.expect(TokenKind::Let)?;
         let var_token = self.token_stream.expect(TokenKind::Identifier)?;
         self.token_stream.expect(TokenKind::Colon)?;
-        let type_token = self.token_stream.expect(TokenKind::Identifier)?;
+        let ty = self.parse_type()?;
         self.token_stream.expect(TokenKind::Eq)?;
         let rhs = self.parse_expr()?;
 
         Ok(Stmt::var_decl(
-            ParsedType::Named(type_token),
+            ty,
             rhs,
             var_token,
+            self.span_from(let_token.span),
+        ))
+    }
+
+    fn parse_const(&mut self) -> Result<Stmt, Diagnostic> {
+        let const_token = self.token_stream.expect(TokenKind::Const)?;
+        let name_token = self.token_stream.expect(TokenKind::Identifier)?;
+        self.token_stream.expect(TokenKind::Colon)?;
+        let ty = self.parse_type()?;
+        self.token_stream.expect(TokenKind::Eq)?;
+        let rhs = self.parse_expr()?;
+
+        Ok(Stmt::const_decl(
+            ty,
+            rhs,
+            name_token,
+            self.span_from(const_token.span),
         ))
     }
 
     fn parse_func(&mut self) -> Result<Stmt, Diagnostic> {
-        self.token_stream.expect(TokenKind::Func)?;
+        let start = self.token_stream.expect(TokenKind::Func)?.span;
         let func_token = self.token_stream.expect(TokenKind::Identifier)?;
         self.token_stream.expect(TokenKind::OpenParen)?;
         let mut params = vec![];
@@ -129,66 +256,156 @@ impl<'ctx> Parser<'ctx> {
         while self.token_stream.peek().kind != TokenKind::CloseParen {
             let token = self.token_stream.expect(TokenKind::Identifier)?;
             self.token_stream.expect(TokenKind::Colon)?;
-            let type_token = self.token_stream.expect(TokenKind::Identifier)?;
-            params.push(ParsedParam {
-                token,
-                ty: ParsedType::Named(type_token),
-            });
+            let ty = self.parse_type()?;
+            params.push(ParsedParam { token, ty });
             if self.token_stream.peek().kind != TokenKind::CloseParen {
                 self.token_stream.expect(TokenKind::Comma)?;
             }
         }
 
         self.token_stream.expect(TokenKind::CloseParen)?;
-        self.token_stream.expect(TokenKind::Colon)?;
-        let ret_token = self.token_stream.expect(TokenKind::Identifier)?;
+        let ret_ty = if self.token_stream.peek().kind == TokenKind::Colon {
+            self.token_stream.advance();
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
         let body = self.parse_block()?;
 
         Ok(Stmt::func_decl(
-            ParsedType::Named(ret_token),
+            ret_ty,
             params,
             body,
             func_token,
+            self.span_from(start),
         ))
     }
 
+    fn parse_struct(&mut self) -> Result<Stmt, Diagnostic> {
+        let start = self.token_stream.expect(TokenKind::Struct)?.span;
+        let name_token = self.token_stream.expect(TokenKind::Identifier)?;
+        self.token_stream.expect(TokenKind::OpenCurly)?;
+        let mut fields = vec![];
+
+        while self.token_stream.peek().kind != TokenKind::CloseCurly {
+            let token = self.token_stream.expect(TokenKind::Identifier)?;
+            self.token_stream.expect(TokenKind::Colon)?;
+            let ty = self.parse_type()?;
+            fields.push(ParsedField { token, ty });
+            if self.token_stream.peek().kind != TokenKind::CloseCurly {
+                self.token_stream.expect(TokenKind::Comma)?;
+            }
+        }
+
+        self.token_stream.expect(TokenKind::CloseCurly)?;
+        Ok(Stmt::struct_decl(fields, name_token, self.span_from(start)))
+    }
+
     fn parse_return(&mut self) -> Result<Stmt, Diagnostic> {
         let token = self.token_stream.expect(TokenKind::Return)?;
-        let expr = self.parse_expr()?;
+        let expr = if self.token_stream.peek().kind == TokenKind::Semi {
+            None
+        } else {
+            Some(self.parse_expr()?)
+        };
 
-        Ok(Stmt::return_stmt(expr, token))
+        let span = self.span_from(token.span);
+        Ok(Stmt::return_stmt(expr, token, span))
     }
 
     fn parse_continue(&mut self) -> Result<Stmt, Diagnostic> {
         let token = self.token_stream.expect(TokenKind::Continue)?;
-        Ok(Stmt::continue_stmt(token))
+        Ok(Stmt::continue_stmt(token.clone(), token.span))
     }
 
     fn parse_break(&mut self) -> Result<Stmt, Diagnostic> {
         let token = self.token_stream.expect(TokenKind::Break)?;
-        Ok(Stmt::break_stmt(token))
+        Ok(Stmt::break_stmt(token.clone(), token.span))
     }
 
     fn parse_empty(&mut self) -> Result<Stmt, Diagnostic> {
         let token = self.token_stream.expect(TokenKind::Semi)?;
-        Ok(Stmt::trivial_stmt(StmtKind::Empty, token))
+        Ok(Stmt::trivial_stmt(
+            StmtKind::Empty,
+            token.clone(),
+            token.span,
+        ))
+    }
+
+    fn parse_type(&mut self) -> Result<ParsedType, Diagnostic> {
+        if self.token_stream.peek().kind == TokenKind::Star {
+            self.token_stream.advance();
+            return Ok(ParsedType::Pointer(Box::new(self.parse_type()?)));
+        }
+        if self.token_stream.peek().kind == TokenKind::Str {
+            self.token_stream.advance();
+            return Ok(ParsedType::Str);
+        }
+        if self.token_stream.peek().kind != TokenKind::OpenBracket {
+            let type_token = self.token_stream.expect(TokenKind::Identifier)?;
+            return Ok(ParsedType::Named(type_token));
+        }
+
+        self.token_stream.expect(TokenKind::OpenBracket)?;
+        let elem = self.parse_type()?;
+        self.token_stream.expect(TokenKind::Semi)?;
+        let len = self.parse_array_len()?;
+        self.token_stream.expect(TokenKind::CloseBracket)?;
+        Ok(ParsedType::Array(Box::new(elem), len))
+    }
+
+    // A length that doesn't fit is still well formed, so parsing carries on as if it were 0
+    fn parse_array_len(&mut self) -> Result<usize, Diagnostic> {
+        let len_token = self.token_stream.expect(TokenKind::Literal)?;
+        let value = match parse_int_literal(&len_token) {
+            Ok(info) => info.value,
+            Err(diag) => {
+                self.report(diag);
+                return Ok(0);
+            }
+        };
+
+        let len = usize::try_from(value).unwrap_or_else(|_| {
+            self.report(Diagnostic::error(
+                DiagnosticKind::InvalidArrayLen {
+                    literal: len_token.lexeme.to_owned(),
+                },
+                len_token.span,
+            ));
+            0
+        });
+        Ok(len)
+    }
+
+    // Span from start up to and including the last consumed token
+    fn span_from(&self, start: SourceSpan) -> SourceSpan {
+        start.to(self.token_stream.prev_span())
     }
 
     fn parse_expr(&mut self) -> Result<Expr, Diagnostic> {
-        Ok(self.parse_expr_recursive(None, 4)?)
+        self.parse_expr_recursive(None, MAX_PRECEDENCE)
     }
 
     fn parse_expr_recursive(&mut self, lhs: Option<Expr>, prec: u32) -> Result<Expr, Diagnostic> {
         if prec == 0 {
             return Ok(match lhs {
                 Some(expr) => expr,
-                None => self.parse_term()?,
+                None => self.parse_cast()?,
             });
         }
 
         let mut lhs = self.parse_expr_recursive(lhs, prec - 1)?;
         let next = self.token_stream.peek();
 
+        // Compound assignments share the (right associative) level of plain assignment
+        if prec == MAX_PRECEDENCE
+            && let Some(op_kind) = get_compound_op(next.kind.clone())
+        {
+            let op = self.token_stream.advance();
+            let rhs = self.parse_expr_recursive(None, prec)?;
+            return Ok(Expr::compound_assign(op_kind, lhs, rhs, op));
+        }
+
         if let Some((op_prec, assoc_kind, op_kind)) = get_op_info(next.kind)
             && op_prec == prec
         {
@@ -202,18 +419,157 @@ impl<'ctx> Parser<'ctx> {
             let rhs = self.parse_expr_recursive(None, right_prec)?;
             lhs = Expr::binary_op(op_kind, lhs, rhs, op);
 
-            if assoc_kind == AssocKind::Left {
-                lhs = self.parse_expr_recursive(Some(lhs), prec)?
+            match assoc_kind {
+                AssocKind::Left => lhs = self.parse_expr_recursive(Some(lhs), prec)?,
+                AssocKind::None => lhs = self.reject_chained(lhs, prec)?,
+                AssocKind::Right => {}
             }
         }
 
         Ok(lhs)
     }
 
+    // `a < b < c` is reported rather than read as `(a < b) < c`, the rest of the chain is still
+    // parsed so the error doesn't cascade
+    fn reject_chained(&mut self, mut lhs: Expr, prec: u32) -> Result<Expr, Diagnostic> {
+        while let Some((op_prec, _, op_kind)) = get_op_info(self.token_stream.peek().kind)
+            && op_prec == prec
+        {
+            let op = self.token_stream.advance();
+            let rhs = self.parse_expr_recursive(None, prec - 1)?;
+            self.report(
+                Diagnostic::error(
+                    DiagnosticKind::ChainedComparison {
+                        op: op.kind.clone(),
+                    },
+                    lhs.span.to(rhs.span),
+                )
+                .with_help("compare each pair separately and join them with `&&`"),
+            );
+            lhs = Expr::binary_op(op_kind, lhs, rhs, op);
+        }
+        Ok(lhs)
+    }
+
+    // Casts bind tighter than any binary operator but looser than the prefix operators, so
+    // `-x as u8` is `(-x) as u8`
+    fn parse_cast(&mut self) -> Result<Expr, Diagnostic> {
+        let mut expr = self.parse_unary()?;
+        while self.token_stream.peek().kind == TokenKind::As {
+            let as_token = self.token_stream.advance();
+            let ty = self.parse_type()?;
+            let span = expr.span.to(self.token_stream.prev_span());
+            expr = Expr::cast(expr, ty, as_token, span);
+        }
+        Ok(expr)
+    }
+
+    // Prefix operators apply to the whole postfix expression like in C, `*p.x` and `&a[i]`
+    fn parse_unary(&mut self) -> Result<Expr, Diagnostic> {
+        let Some(op_kind) = get_unary_op(self.token_stream.peek().kind) else {
+            return self.parse_postfix();
+        };
+
+        let op = self.token_stream.advance();
+        let expr = self.parse_unary()?;
+        Ok(match op_kind {
+            UnOpKind::Neg => negate(expr, op, &self.ctx.source),
+            _ => Expr::unary_op(op_kind, expr, op),
+        })
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, Diagnostic> {
+        let mut expr = self.parse_term()?;
+        loop {
+            match self.token_stream.peek().kind {
+                TokenKind::OpenBracket => {
+                    let bracket = self.token_stream.advance();
+                    let index = self.parse_expr()?;
+                    self.token_stream.expect(TokenKind::CloseBracket)?;
+                    let span = expr.span.to(self.token_stream.prev_span());
+                    expr = Expr::index(expr, index, bracket, span);
+                }
+                TokenKind::Dot => {
+                    self.token_stream.advance();
+                    let field = self.token_stream.expect(TokenKind::Identifier)?;
+                    let span = expr.span.to(field.span);
+                    expr = Expr::field(expr, field, span);
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    // `Name { field: value, ... }`. A name followed by a block is far more common, think
+    // `if done { ... }`, so it only counts as a literal when the brace opens with `field:`
+    fn is_struct_literal(&self) -> bool {
+        self.token_stream.peek().kind == TokenKind::OpenCurly
+            && self.token_stream.peek_nth(1).kind == TokenKind::Identifier
+            && self.token_stream.peek_nth(2).kind == TokenKind::Colon
+    }
+
+    fn parse_struct_literal(&mut self, name_token: Token) -> Result<Expr, Diagnostic> {
+        self.token_stream.expect(TokenKind::OpenCurly)?;
+        let mut fields = vec![];
+
+        while self.token_stream.peek().kind != TokenKind::CloseCurly {
+            let field = self.token_stream.expect(TokenKind::Identifier)?;
+            self.token_stream.expect(TokenKind::Colon)?;
+            fields.push((field, self.parse_expr()?));
+            if self.token_stream.peek().kind != TokenKind::CloseCurly {
+                self.token_stream.expect(TokenKind::Comma)?;
+            }
+        }
+
+        self.token_stream.expect(TokenKind::CloseCurly)?;
+        let span = self.span_from(name_token.span);
+        Ok(Expr::struct_lit(fields, name_token, span))
+    }
+
+    // Either a list of elements `[a, b, c]` or a repeated value `[v; N]`
+    fn parse_array(&mut self, bracket: Token) -> Result<Expr, Diagnostic> {
+        let mut elems = vec![];
+        while self.token_stream.peek().kind != TokenKind::CloseBracket {
+            elems.push(self.parse_expr()?);
+
+            if elems.len() == 1 && self.token_stream.peek().kind == TokenKind::Semi {
+                self.token_stream.advance();
+                let count = self.parse_array_len()?;
+                self.token_stream.expect(TokenKind::CloseBracket)?;
+                let span = self.span_from(bracket.span);
+                return Ok(Expr::array_repeat(
+                    elems.pop().unwrap(),
+                    count,
+                    bracket,
+                    span,
+                ));
+            }
+
+            if self.token_stream.peek().kind != TokenKind::CloseBracket {
+                self.token_stream.expect(TokenKind::Comma)?;
+            }
+        }
+
+        self.token_stream.expect(TokenKind::CloseBracket)?;
+        let span = self.span_from(bracket.span);
+        Ok(Expr::array(elems, bracket, span))
+    }
+
     fn parse_term(&mut self) -> Result<Expr, Diagnostic> {
-        let token = self.token_stream.advance();
+        let token = self.token_stream.peek();
+        if !token.kind.starts_expression() {
+            return Err(Diagnostic::error(
+                DiagnosticKind::UnexpectedTokenInExpression { found: token.kind },
+                token.span,
+            ));
+        }
+
+        self.token_stream.advance();
         match token.kind {
             TokenKind::Identifier => {
+                if self.is_struct_literal() {
+                    return self.parse_struct_literal(token);
+                }
                 if self.token_stream.peek().kind != TokenKind::OpenParen {
                     return Ok(Expr::var(token));
                 }
@@ -232,36 +588,125 @@ impl<'ctx> Parser<'ctx> {
 
                 self.token_stream.expect(TokenKind::CloseParen)?;
 
-                Ok(Expr::func(args, token))
+                let span = self.span_from(token.span);
+                Ok(Expr::func(args, token, span))
             }
             TokenKind::Literal => {
-                let val: i64 = token.lexeme.parse().map_err(|_| Diagnostic {
-                    line: token.line,
-                    kind: DiagnosticKind::NumLiteralTooLarge {
-                        literal: token.lexeme.to_owned(),
-                    },
-                })?;
-                Ok(Expr::lit(val, token))
+                let info = parse_int_literal(&token)?;
+                Ok(Expr::lit(info, token))
             }
+            TokenKind::StrLiteral => Ok(Expr::str_lit(token)),
+            TokenKind::CharLiteral => Ok(Expr::char_lit(token)),
+            TokenKind::OpenBracket => self.parse_array(token),
+            TokenKind::True => Ok(Expr::bool_lit(true, token)),
+            TokenKind::False => Ok(Expr::bool_lit(false, token)),
             TokenKind::OpenParen => {
                 let expr = self.parse_expr()?;
                 self.token_stream.expect(TokenKind::CloseParen)?;
                 Ok(expr)
             }
-            TokenKind::Bang => {
-                let expr = self.parse_expr()?;
-                Ok(Expr::unary_op(UnOpKind::Not, expr, token))
-            }
-            TokenKind::Minus => {
-                let expr = self.parse_expr()?;
-                Ok(Expr::unary_op(UnOpKind::Neg, expr, token))
-            }
-            _ => Err(Diagnostic {
-                line: token.line,
-                kind: DiagnosticKind::UnexpectedTokenInExpression { found: token.kind },
-            }),
+            _ => unreachable!("prefix operators are parsed by parse_unary"),
+        }
+    }
+}
+
+// A `-` directly against a literal is folded into it, so `-9223372036854775808` fits in an i64
+// even though its digits alone don't. `-(9223372036854775808)` is still a negated operand
+fn negate(mut expr: Expr, minus: Token, source: &Source) -> Expr {
+    let adjacent = minus.span.high == expr.span.low;
+    let ExprKind::Literal(info) = &mut expr.kind else {
+        return Expr::unary_op(UnOpKind::Neg, expr, minus);
+    };
+    if !adjacent {
+        return Expr::unary_op(UnOpKind::Neg, expr, minus);
+    }
+
+    info.value = -info.value;
+    expr.span = minus.span.to(expr.span);
+    expr.token.lexeme = source.get_spanned(&expr.span).to_owned();
+    expr.token.span = expr.span;
+    expr
+}
+
+// Splits a literal like `0xff_u8` into its value and type suffix. Whether the value fits is
+// only known once the type is, values too large for any type saturate until then
+fn parse_int_literal(token: &Token) -> Result<LiteralInfo, Diagnostic> {
+    let lexeme = token.lexeme.as_str();
+    let (radix, start) = match lexeme.get(..2) {
+        Some("0x") => (16, 2),
+        Some("0o") => (8, 2),
+        Some("0b") => (2, 2),
+        _ => (10, 0),
+    };
+
+    // Any decimal digit is taken so `0b102` points at the 2 rather than an odd suffix
+    let is_digit = |c: char| c == '_' || c.is_digit(radix.max(10));
+    let end = lexeme[start..]
+        .find(|c| !is_digit(c))
+        .map_or(lexeme.len(), |len| start + len);
+
+    let mut value: u128 = 0;
+    let mut has_digits = false;
+    for (offset, c) in lexeme[start..end].char_indices() {
+        if c == '_' {
+            continue;
         }
+        let Some(digit) = c.to_digit(radix) else {
+            let offset = start + offset;
+            return Err(Diagnostic::error(
+                DiagnosticKind::InvalidDigit { digit: c, radix },
+                token.span.slice(offset, offset + 1),
+            ));
+        };
+        value = value
+            .saturating_mul(radix as u128)
+            .saturating_add(digit as u128);
+        has_digits = true;
+    }
+
+    if !has_digits {
+        return Err(Diagnostic::error(
+            DiagnosticKind::MissingDigits {
+                literal: lexeme.to_owned(),
+            },
+            token.span,
+        ));
     }
+
+    let suffix = match &lexeme[end..] {
+        "" => None,
+        suffix if INT_SUFFIXES.contains(&suffix) => Some(suffix.to_owned()),
+        suffix => {
+            return Err(Diagnostic::error(
+                DiagnosticKind::InvalidLiteralSuffix {
+                    suffix: suffix.to_owned(),
+                },
+                token.span.slice(end, lexeme.len()),
+            ));
+        }
+    };
+
+    Ok(LiteralInfo {
+        value: value.min(i128::MAX as u128) as i128,
+        suffix,
+    })
+}
+
+// Arithmetic operator applied by a compound assignment token
+fn get_compound_op(kind: TokenKind) -> Option<BinOpKind> {
+    Some(match kind {
+        TokenKind::PlusEq => BinOpKind::Add,
+        TokenKind::MinusEq => BinOpKind::Sub,
+        TokenKind::StarEq => BinOpKind::Mult,
+        TokenKind::SlashEq => BinOpKind::Div,
+        TokenKind::PercentEq => BinOpKind::Mod,
+        TokenKind::AmpEq => BinOpKind::BitAnd,
+        TokenKind::PipeEq => BinOpKind::BitOr,
+        TokenKind::CaretEq => BinOpKind::BitXor,
+        TokenKind::LessLessEq => BinOpKind::Shl,
+        TokenKind::GreaterGreaterEq => BinOpKind::Shr,
+        _ => return None,
+    })
 }
 
 #[derive(PartialEq)]
@@ -271,20 +716,41 @@ enum AssocKind {
     None,
 }
 
+// Prefix operators bind tighter than every binary operator, see parse_unary
+fn get_unary_op(kind: TokenKind) -> Option<UnOpKind> {
+    Some(match kind {
+        TokenKind::Bang => UnOpKind::Not,
+        TokenKind::Minus => UnOpKind::Neg,
+        TokenKind::Tilde => UnOpKind::BitNot,
+        TokenKind::Amp => UnOpKind::AddrOf,
+        TokenKind::Star => UnOpKind::Deref,
+        _ => return None,
+    })
+}
+
 // </3
+// Same levels as C, from tightest (1) to loosest (MAX_PRECEDENCE). Comparisons don't chain
 fn get_op_info(kind: TokenKind) -> Option<(u32, AssocKind, BinOpKind)> {
     Some(match kind {
-        TokenKind::Eq => (4, AssocKind::Right, BinOpKind::Assign),
-        TokenKind::EqEq => (3, AssocKind::None, BinOpKind::Equals),
-        TokenKind::BangEq => (3, AssocKind::None, BinOpKind::NotEquals),
-        TokenKind::LessThan => (3, AssocKind::None, BinOpKind::LessThan),
-        TokenKind::LessEq => (3, AssocKind::None, BinOpKind::LessEq),
-        TokenKind::GreaterThan => (3, AssocKind::None, BinOpKind::GreaterThan),
-        TokenKind::GreaterEq => (3, AssocKind::None, BinOpKind::GreaterEq),
+        TokenKind::Eq => (11, AssocKind::Right, BinOpKind::Assign),
+        TokenKind::PipePipe => (10, AssocKind::Left, BinOpKind::Or),
+        TokenKind::AmpAmp => (9, AssocKind::Left, BinOpKind::And),
+        TokenKind::Pipe => (8, AssocKind::Left, BinOpKind::BitOr),
+        TokenKind::Caret => (7, AssocKind::Left, BinOpKind::BitXor),
+        TokenKind::Amp => (6, AssocKind::Left, BinOpKind::BitAnd),
+        TokenKind::EqEq => (5, AssocKind::None, BinOpKind::Equals),
+        TokenKind::BangEq => (5, AssocKind::None, BinOpKind::NotEquals),
+        TokenKind::LessThan => (4, AssocKind::None, BinOpKind::LessThan),
+        TokenKind::LessEq => (4, AssocKind::None, BinOpKind::LessEq),
+        TokenKind::GreaterThan => (4, AssocKind::None, BinOpKind::GreaterThan),
+        TokenKind::GreaterEq => (4, AssocKind::None, BinOpKind::GreaterEq),
+        TokenKind::LessLess => (3, AssocKind::Left, BinOpKind::Shl),
+        TokenKind::GreaterGreater => (3, AssocKind::Left, BinOpKind::Shr),
         TokenKind::Plus => (2, AssocKind::Left, BinOpKind::Add),
         TokenKind::Minus => (2, AssocKind::Left, BinOpKind::Sub),
         TokenKind::Star => (1, AssocKind::Left, BinOpKind::Mult),
         TokenKind::Slash => (1, AssocKind::Left, BinOpKind::Div),
+        TokenKind::Percent => (1, AssocKind::Left, BinOpKind::Mod),
         _ => {
             return None;
         }
@@ -1,12 +1,15 @@
+use crate::abi;
 use crate::ast::{
-    BinOpInfo, BinOpKind, Expr, ExprKind, FuncCallInfo, FuncDeclInfo, IfInfo, Program, ReturnInfo,
-    Stmt, StmtKind, UnOpInfo, VarDeclInfo, WhileInfo,
+    ArrayInfo, ArrayRepeatInfo, BinOpInfo, BinOpKind, CastInfo, CompoundAssignInfo, Expr, ExprKind,
+    FieldInfo, FuncCallInfo, FuncDeclInfo, IfInfo, IndexInfo, LiteralInfo, Program, ReturnInfo,
+    Stmt, StmtKind, StructLitInfo, UnOpInfo, UnOpKind, VarDeclInfo, WhileInfo,
 };
 use crate::compiler::Context;
 use crate::diagnostic::{Diagnostic, DiagnosticKind};
-use crate::symbols::SymbolID;
+use crate::parser::ParsedType;
 use crate::symbols::Symbols;
-use crate::tokens::Token;
+use crate::symbols::{ResolvedType, StaticData, Storage, SymbolID};
+use crate::tokens::{SourceSpan, Token};
 
 use core::fmt;
 use std::cell::{Ref, RefMut};
@@ -15,6 +18,7 @@ use std::cell::{Ref, RefMut};
 pub struct LoopID(usize);
 
 impl LoopID {
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Self {
         let current = *self;
         self.0 += 1;
@@ -32,6 +36,7 @@ impl fmt::Display for LoopID {
 pub struct IfID(usize);
 
 impl IfID {
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Self {
         let current = *self;
         self.0 += 1;
@@ -62,7 +67,7 @@ pub struct SemanticAnalyzer<'ctx> {
 impl<'ctx> SemanticAnalyzer<'ctx> {
     pub fn new(ctx: &'ctx Context) -> Self {
         SemanticAnalyzer {
-            ctx: ctx,
+            ctx,
             next_loop_id: LoopID(0),
             current_loop: None,
             next_if_id: IfID(0),
@@ -70,20 +75,184 @@ impl<'ctx> SemanticAnalyzer<'ctx> {
         }
     }
 
-    // In the future, we could syncronize to catch multiple errors
     pub fn analyze(&mut self, ast: &mut Program) {
+        self.declare_structs(ast);
+        self.declare_funcs(ast);
+
+        // Globals and constants go first so every function body can see them
+        let (funcs, statics): (Vec<_>, Vec<_>) = ast
+            .top
+            .iter_mut()
+            .partition(|stmt| matches!(stmt.kind, StmtKind::FuncDecl(_)));
+        for stmt in statics.into_iter().chain(funcs) {
+            self.analyze_statement_reporting(stmt);
+        }
+
+        if let Err(diag) = self.validate_main() {
+            self.report(diag);
+        }
+        self.report_unused(&[]);
+    }
+
+    // Struct names are all registered before any fields are resolved, so fields can use structs
+    // declared further down the file
+    fn declare_structs(&mut self, ast: &mut Program) {
         for stmt in &mut ast.top {
-            match self.analyze_statement(stmt) {
-                Ok(_) => {}
-                Err(diag) => {
-                    self.ctx.diags.borrow_mut().report(diag);
-                    return;
+            let StmtKind::StructDecl(info) = &mut stmt.kind else {
+                continue;
+            };
+            match self.symbols_mut().register_struct(&stmt.token) {
+                Ok(struct_id) => info.id = Some(struct_id),
+                Err(diag) => self.report(diag),
+            }
+        }
+
+        let decls: Vec<(SymbolID, &Stmt)> = ast
+            .top
+            .iter()
+            .filter_map(|stmt| match &stmt.kind {
+                StmtKind::StructDecl(info) => Some((info.id?, stmt)),
+                _ => None,
+            })
+            .collect();
+        for (struct_id, _) in &decls {
+            self.layout_type(&ResolvedType::Named(*struct_id), &decls, &mut vec![]);
+        }
+    }
+
+    // Lays out any struct a type holds by value, which has to happen before the size of the type
+    // is known. Returns false if that leads back to a struct already being laid out
+    fn layout_type(
+        &mut self,
+        ty: &ResolvedType,
+        decls: &[(SymbolID, &Stmt)],
+        visiting: &mut Vec<SymbolID>,
+    ) -> bool {
+        let struct_id = match ty {
+            ResolvedType::Array(elem, _) => return self.layout_type(elem, decls, visiting),
+            // Pointers have the same size whatever they point to, so a struct can point to itself
+            ResolvedType::Pointer(_) | ResolvedType::Str => return true,
+            ResolvedType::Named(id) => *id,
+        };
+        let Some((_, stmt)) = decls.iter().find(|(id, _)| *id == struct_id) else {
+            return true;
+        };
+        if self.symbols().struct_info(ty).is_some() {
+            return true;
+        }
+        if visiting.contains(&struct_id) {
+            self.report(Diagnostic::error(
+                DiagnosticKind::RecursiveStruct {
+                    struct_name: stmt.token.lexeme.to_owned(),
+                },
+                stmt.token.span,
+            ));
+            return false;
+        }
+
+        let StmtKind::StructDecl(info) = &stmt.kind else {
+            unreachable!("only struct declarations are collected")
+        };
+        if info.fields.is_empty() {
+            self.report(Diagnostic::error(
+                DiagnosticKind::EmptyStruct {
+                    struct_name: stmt.token.lexeme.to_owned(),
+                },
+                stmt.token.span,
+            ));
+        }
+
+        visiting.push(struct_id);
+        let mut fields: Vec<(String, ResolvedType)> = vec![];
+        for field in &info.fields {
+            let name = &field.token.lexeme;
+            let mut field_ty = self.resolve_type_reporting(&field.ty);
+            if !self.layout_type(&field_ty, decls, visiting) {
+                field_ty = self.symbols().error_type();
+            }
+
+            if self.symbols().is_void(&field_ty) {
+                self.report(Diagnostic::error(
+                    DiagnosticKind::VoidField {
+                        field: name.to_owned(),
+                    },
+                    field.token.span,
+                ));
+            }
+            if fields.iter().any(|(seen, _)| seen == name) {
+                self.report(Diagnostic::error(
+                    DiagnosticKind::DuplicateField {
+                        field: name.to_owned(),
+                    },
+                    field.token.span,
+                ));
+                continue;
+            }
+            fields.push((name.to_owned(), field_ty));
+        }
+        visiting.pop();
+
+        self.symbols_mut().define_struct(struct_id, fields);
+        true
+    }
+
+    // Registers every function signature up front so bodies can call functions declared
+    // further down the file
+    fn declare_funcs(&mut self, ast: &mut Program) {
+        for stmt in &mut ast.top {
+            let StmtKind::FuncDecl(info) = &mut stmt.kind else {
+                continue;
+            };
+
+            let param_tys = info
+                .params
+                .iter()
+                .map(|param| {
+                    let ty = self.resolve_type_reporting(&param.ty);
+                    self.expect_non_array_sig(ty, param.token.span)
+                })
+                .collect();
+            let return_ty = match &info.ty {
+                Some(ty) => {
+                    let ty = self.resolve_type_reporting(ty);
+                    self.expect_non_array_sig(ty, stmt.token.span)
                 }
+                None => self.symbols().primitive_type("void"),
+            };
+
+            let registered = self
+                .symbols_mut()
+                .register_func(&stmt.token, return_ty, param_tys);
+            match registered {
+                Ok(func_id) => info.id = Some(func_id),
+                Err(diag) => self.report(diag),
             }
         }
+    }
 
-        if let Err(diag) = self.validate_main() {
-            self.ctx.diags.borrow_mut().report(diag)
+    // Arrays are only ever passed around by address, which the calling convention has no
+    // room for yet. Structs are copied in and out by value
+    fn expect_non_array_sig(&self, ty: ResolvedType, span: SourceSpan) -> ResolvedType {
+        if !matches!(ty, ResolvedType::Array(..)) {
+            return ty;
+        }
+        self.report(Diagnostic::error(DiagnosticKind::ArrayInSignature, span));
+        self.symbols().error_type()
+    }
+
+    // Unknown types are reported and replaced by the error type so analysis can go on
+    fn resolve_type_reporting(&self, ty: &ParsedType) -> ResolvedType {
+        let resolved = self.symbols().resolve_type(ty);
+        resolved.unwrap_or_else(|diag| {
+            self.report(diag);
+            self.symbols().error_type()
+        })
+    }
+
+    // Errors that abandon a statement are reported here so its siblings still get analyzed
+    fn analyze_statement_reporting(&mut self, stmt: &mut Stmt) {
+        if let Err(diag) = self.analyze_statement(stmt) {
+            self.report(diag);
         }
     }
 
@@ -94,11 +263,18 @@ impl<'ctx> SemanticAnalyzer<'ctx> {
         match &mut stmt.kind {
             StmtKind::Empty => {}
             StmtKind::If(info) => self.analyze_if(info)?,
-            StmtKind::ExprStmt(expr) => self.analyze_expr(expr)?,
+            StmtKind::ExprStmt(expr) => {
+                self.analyze_expr(expr)?;
+            }
             StmtKind::Block(stmts) => self.analyze_block(stmts)?,
             StmtKind::While(info) => self.analyze_while(info)?,
+            StmtKind::VarDecl(info) if self.current_function.is_none() => {
+                self.analyze_global(info, stmt.token.clone())?
+            }
             StmtKind::VarDecl(info) => self.analyze_var(info, stmt.token.clone())?,
+            StmtKind::ConstDecl(info) => self.analyze_const(info, stmt.token.clone())?,
             StmtKind::FuncDecl(info) => self.analyze_func(info, stmt.token.clone())?,
+            StmtKind::StructDecl(_) => {} // Declared along with every other struct up front
             StmtKind::Continue(id) => self.analyze_continue(id, stmt.token.clone())?,
             StmtKind::Break(id) => self.analyze_break(id, stmt.token.clone())?,
             StmtKind::Return(info) => self.analyze_return(info, stmt.token.clone())?,
@@ -116,83 +292,458 @@ impl<'ctx> SemanticAnalyzer<'ctx> {
         } = info;
 
         *id = Some(self.next_if_id.next());
-        self.analyze_expr(cond)?;
-        self.analyze_statement(do_if)?;
+        self.analyze_condition(cond)?;
+        self.analyze_statement_reporting(do_if);
         if let Some(do_else) = do_else {
-            self.analyze_statement(do_else)?;
+            self.analyze_statement_reporting(do_else);
         }
         Ok(())
     }
 
     fn analyze_block(&mut self, stmts: &mut Vec<Stmt>) -> Result<(), Diagnostic> {
         self.symbols_mut().push_scope();
-        self.analyze_block_inner(stmts)?;
+        self.analyze_block_inner(stmts);
+        self.report_unused(&[]);
         self.symbols_mut().pop_scope();
         Ok(())
     }
 
-    fn analyze_block_inner(&mut self, stmts: &mut Vec<Stmt>) -> Result<(), Diagnostic> {
+    fn analyze_block_inner(&mut self, stmts: &mut Vec<Stmt>) {
         for stmt in stmts {
-            self.analyze_statement(stmt)?;
+            self.analyze_statement_reporting(stmt);
         }
-        Ok(())
     }
 
     fn analyze_var(&mut self, info: &mut VarDeclInfo, var_token: Token) -> Result<(), Diagnostic> {
         let VarDeclInfo { id, ty, expr } = info;
-        self.analyze_expr(expr)?;
-        let symbol_id =
+        let declared_ty = self.symbols().resolve_type(ty).ok();
+        if let Some(declared_ty) = &declared_ty {
+            self.expect_non_void_var(declared_ty, &var_token);
+        }
+        let expr_ty = self.analyze_expr_hinted(expr, declared_ty.as_ref())?;
+        let registered =
             self.symbols_mut()
-                .register_var(&var_token, &ty, self.current_function.unwrap())?;
-        *id = Some(symbol_id);
+                .register_var(&var_token, ty, self.current_function.unwrap());
+
+        match registered {
+            Ok(symbol_id) => {
+                *id = Some(symbol_id);
+                let var_ty = self.symbols().var_type(symbol_id);
+                self.expect_type(&expr_ty, &var_ty, expr.span);
+            }
+            Err(diag) => {
+                // Keep later uses of the variable from being reported as unknown
+                if matches!(diag.kind, DiagnosticKind::TypeUnknown { .. }) {
+                    self.symbols_mut().register_poison(&var_token);
+                }
+                return Err(diag);
+            }
+        }
+        Ok(())
+    }
+
+    // Globals live in static data, so their initial value has to be known at compile time
+    fn analyze_global(
+        &mut self,
+        info: &mut VarDeclInfo,
+        var_token: Token,
+    ) -> Result<(), Diagnostic> {
+        let errors = self.ctx.diags.borrow().error_count();
+        let ty = self.analyze_static(info, &var_token)?;
+        let size = self.symbols().size_of(&ty);
+
+        // An initializer that failed to type check, say a field with the wrong array length,
+        // doesn't have the layout of its type and can't be folded into it
+        let data = if self.ctx.diags.borrow().error_count() > errors {
+            StaticData::zeroed(size)
+        } else {
+            self.eval_static(&info.expr, &ty).unwrap_or_else(|diag| {
+                self.report(diag);
+                StaticData::zeroed(size)
+            })
+        };
+        let registered = self
+            .symbols_mut()
+            .register_static(&var_token, ty, Storage::Global(data));
+        info.id = Some(registered?);
+        Ok(())
+    }
+
+    fn analyze_const(
+        &mut self,
+        info: &mut VarDeclInfo,
+        var_token: Token,
+    ) -> Result<(), Diagnostic> {
+        let errors = self.ctx.diags.borrow().error_count();
+        let ty = self.analyze_static(info, &var_token)?;
+        let value = if self.symbols().is_aggregate(&ty) {
+            // Constants are inlined as immediates, which an array can't be
+            self.report(Diagnostic::error(
+                DiagnosticKind::AggregateConst {
+                    const_name: var_token.lexeme.to_owned(),
+                    ty: self.symbols().type_name(&ty),
+                },
+                var_token.span,
+            ));
+            0
+        } else if self.ctx.diags.borrow().error_count() > errors {
+            // Already reported, folding a mistyped initializer would only pile on more errors
+            0
+        } else {
+            self.eval_const(&info.expr).unwrap_or_else(|diag| {
+                self.report(diag);
+                0
+            })
+        };
+        let registered = self
+            .symbols_mut()
+            .register_static(&var_token, ty, Storage::Const(value));
+        info.id = Some(registered?);
         Ok(())
     }
 
+    // Type checks a global or constant declaration, the caller folds its initializer
+    fn analyze_static(
+        &mut self,
+        info: &mut VarDeclInfo,
+        var_token: &Token,
+    ) -> Result<ResolvedType, Diagnostic> {
+        let VarDeclInfo { ty, expr, .. } = info;
+        let resolved = self.symbols().resolve_type(ty);
+        let declared_ty = match resolved {
+            Ok(declared_ty) => declared_ty,
+            Err(diag) => {
+                self.symbols_mut().register_poison(var_token);
+                return Err(diag);
+            }
+        };
+        self.expect_non_void_var(&declared_ty, var_token);
+
+        let expr_ty = self.analyze_expr_hinted(expr, Some(&declared_ty))?;
+        self.expect_type(&expr_ty, &declared_ty, expr.span);
+        Ok(declared_ty)
+    }
+
+    // Lays out the initial value of a global of type `ty`, little endian like the target.
+    // Padding between struct fields is zeroed
+    fn eval_static(&self, expr: &Expr, ty: &ResolvedType) -> Result<StaticData, Diagnostic> {
+        if let ExprKind::StructLit(StructLitInfo { fields, .. }) = &expr.kind {
+            let mut data = StaticData::zeroed(self.symbols().size_of(ty));
+            for init in fields {
+                let field = self
+                    .symbols()
+                    .field(ty, &init.token.lexeme)
+                    .map(|field| (field.ty.clone(), field.offset));
+                // Unknown fields were already reported
+                let Some((field_ty, offset)) = field else {
+                    continue;
+                };
+                data.write(offset, self.eval_static(&init.expr, &field_ty)?);
+            }
+            return Ok(data);
+        }
+
+        // Laid out like gen_strings lays out the header of a literal
+        if let ExprKind::Str(contents) = &expr.kind {
+            let mut data = StaticData::zeroed(8);
+            data.bytes.extend((contents.len() as u64).to_le_bytes());
+            data.strings.push((0, contents.to_owned()));
+            return Ok(data);
+        }
+
+        let elem = self.symbols().elem_type(ty);
+        match (&expr.kind, elem) {
+            (ExprKind::Array(ArrayInfo { elems, .. }), Some((elem_ty, _))) => {
+                let mut data = StaticData::zeroed(0);
+                for elem in elems {
+                    data.write(data.bytes.len(), self.eval_static(elem, &elem_ty)?);
+                }
+                Ok(data)
+            }
+            (ExprKind::ArrayRepeat(ArrayRepeatInfo { value, count, .. }), Some((elem_ty, _))) => {
+                let value = self.eval_static(value, &elem_ty)?;
+                let mut data = StaticData::zeroed(0);
+                for _ in 0..*count {
+                    data.write(data.bytes.len(), value.clone());
+                }
+                Ok(data)
+            }
+            (_, Some(_)) => Err(Diagnostic::error(DiagnosticKind::NotConstant, expr.span)),
+            (_, None) => {
+                let value = self.eval_const(expr)?;
+                let size = self.symbols().size_of(ty);
+                Ok(StaticData {
+                    bytes: value.to_le_bytes()[..size].to_vec(),
+                    strings: vec![],
+                })
+            }
+        }
+    }
+
+    // Folds an analyzed expression down to its value. Operations that trap on overflow in debug
+    // builds are rejected when they overflow, whatever the build mode, the rest wrap exactly like
+    // the generated code would
+    fn eval_const(&self, expr: &Expr) -> Result<i64, Diagnostic> {
+        let not_constant = || Diagnostic::error(DiagnosticKind::NotConstant, expr.span);
+        let value = match &expr.kind {
+            ExprKind::Literal(info) => info.value as i64,
+            ExprKind::Bool(val) => *val as i64,
+            ExprKind::Char(val) => *val as i64,
+            ExprKind::Var(Some(id)) => self.symbols().const_value(*id).ok_or_else(not_constant)?,
+            ExprKind::Cast(info) => self.eval_const(&info.expr)?,
+            ExprKind::UnOp(UnOpInfo {
+                op: UnOpKind::AddrOf | UnOpKind::Deref,
+                ..
+            }) => return Err(not_constant()),
+            ExprKind::UnOp(UnOpInfo { op, expr }) => {
+                let val = self.eval_const(expr)?;
+                match op {
+                    UnOpKind::Neg => self.fit_const(Some(-(val as i128)), expr)?,
+                    UnOpKind::Not => (val == 0) as i64,
+                    UnOpKind::BitNot => !val,
+                    UnOpKind::AddrOf | UnOpKind::Deref => unreachable!(),
+                }
+            }
+            ExprKind::BinOp(BinOpInfo { op, lhs, rhs }) => {
+                let l = self.eval_const(lhs)?;
+                let r = self.eval_const(rhs)?;
+
+                // Pointers move by whole elements, unchecked like the generated code
+                let pointee = self.symbols().pointee_type(lhs.ty.as_ref().unwrap());
+                if let Some(pointee) = pointee
+                    && matches!(op, BinOpKind::Add | BinOpKind::Sub)
+                {
+                    let offset = r.wrapping_mul(self.symbols().size_of(&pointee) as i64);
+                    return Ok(if matches!(op, BinOpKind::Add) {
+                        l.wrapping_add(offset)
+                    } else {
+                        l.wrapping_sub(offset)
+                    });
+                }
+
+                let signed = self.symbols().is_signed(lhs.ty.as_ref().unwrap());
+                let (ul, ur) = (l as u64, r as u64);
+                // Widened without losing the sign, so the checked operations can't overflow here
+                let (wl, wr) = if signed {
+                    (l as i128, r as i128)
+                } else {
+                    (ul as i128, ur as i128)
+                };
+                match op {
+                    BinOpKind::Assign => return Err(not_constant()),
+                    BinOpKind::Div | BinOpKind::Mod if r == 0 => {
+                        return Err(Diagnostic::error(DiagnosticKind::ConstDivByZero, expr.span));
+                    }
+                    BinOpKind::Add => self.fit_const(wl.checked_add(wr), expr)?,
+                    BinOpKind::Sub => self.fit_const(wl.checked_sub(wr), expr)?,
+                    BinOpKind::Mult => self.fit_const(wl.checked_mul(wr), expr)?,
+                    BinOpKind::Div => self.fit_const(wl.checked_div(wr), expr)?,
+                    // Only `i64::MIN % -1` overflows, narrower operands are still sign extended
+                    BinOpKind::Mod if signed => {
+                        self.fit_const(l.checked_rem(r).map(i128::from), expr)?
+                    }
+                    BinOpKind::Mod => (ul % ur) as i64,
+                    BinOpKind::BitAnd => l & r,
+                    BinOpKind::BitOr => l | r,
+                    BinOpKind::BitXor => l ^ r,
+                    BinOpKind::Shl => l.wrapping_shl(r as u32),
+                    BinOpKind::Shr if signed => l.wrapping_shr(r as u32),
+                    BinOpKind::Shr => ul.wrapping_shr(r as u32) as i64,
+                    BinOpKind::Equals => (l == r) as i64,
+                    BinOpKind::NotEquals => (l != r) as i64,
+                    BinOpKind::LessThan if signed => (l < r) as i64,
+                    BinOpKind::LessEq if signed => (l <= r) as i64,
+                    BinOpKind::GreaterThan if signed => (l > r) as i64,
+                    BinOpKind::GreaterEq if signed => (l >= r) as i64,
+                    BinOpKind::LessThan => (ul < ur) as i64,
+                    BinOpKind::LessEq => (ul <= ur) as i64,
+                    BinOpKind::GreaterThan => (ul > ur) as i64,
+                    BinOpKind::GreaterEq => (ul >= ur) as i64,
+                    BinOpKind::And => (l != 0 && r != 0) as i64,
+                    BinOpKind::Or => (l != 0 || r != 0) as i64,
+                }
+            }
+            ExprKind::Var(None)
+            | ExprKind::Func(_)
+            | ExprKind::CompoundAssign(_)
+            | ExprKind::Index(_)
+            | ExprKind::Array(_)
+            | ExprKind::ArrayRepeat(_)
+            | ExprKind::Field(_)
+            | ExprKind::StructLit(_)
+            | ExprKind::Str(_) => {
+                return Err(not_constant());
+            }
+        };
+        Ok(self
+            .symbols()
+            .wrap_to_type(value, expr.ty.as_ref().unwrap()))
+    }
+
+    // A folded value has to fit the type of its expression, like the runtime overflow checks demand
+    fn fit_const(&self, value: Option<i128>, expr: &Expr) -> Result<i64, Diagnostic> {
+        let ty = expr.ty.as_ref().unwrap();
+        let (min, max) = self.symbols().int_range(ty);
+        match value {
+            Some(value) if (min..=max).contains(&value) => Ok(value as i64),
+            _ => Err(Diagnostic::error(
+                DiagnosticKind::ConstOverflow {
+                    ty: self.symbols().type_name(ty),
+                },
+                expr.span,
+            )),
+        }
+    }
+
     fn analyze_func(
         &mut self,
         info: &mut FuncDeclInfo,
         func_token: Token,
     ) -> Result<(), Diagnostic> {
         let FuncDeclInfo {
-            id,
-            ty,
-            params,
-            body,
+            id, params, body, ..
         } = info;
 
-        let prev = self.current_function.take();
-        let func_id = self.symbols_mut().register_func(&func_token, ty)?;
-        *id = Some(func_id);
-        self.current_function = *id;
+        // Functions that failed to declare were already reported by declare_funcs
+        let Some(func_id) = *id else {
+            return Ok(());
+        };
+        let prev = self.current_function.replace(func_id);
+
+        let return_ty = self.symbols().func_info(func_id).return_ty.clone();
+        if abi::returns_in_memory(&self.symbols(), &return_ty) {
+            self.symbols_mut().reserve_return_slot(func_id);
+        }
 
         self.symbols_mut().push_scope();
-        for param in params {
-            self.symbols_mut()
-                .register_param(&param.token, &param.ty, func_id)?;
+        for (index, param) in params.iter().enumerate() {
+            let param_ty = self.symbols().func_info(func_id).param_tys[index].clone();
+            self.expect_non_void_var(&param_ty, &param.token);
+            let registered = self
+                .symbols_mut()
+                .register_param(&param.token, param_ty, func_id);
+            if let Err(diag) = registered {
+                self.report(diag);
+                self.symbols_mut().register_poison(&param.token);
+            }
         }
 
         let StmtKind::Block(stmts) = &mut body.kind else {
             unreachable!("func body must be a block")
         };
-        self.analyze_block_inner(stmts)?;
+        self.analyze_block_inner(stmts);
+        let params = self.symbols().func_info(func_id).params.clone();
+        self.report_unused(&params);
         self.symbols_mut().pop_scope();
 
+        let returns_void = self
+            .symbols()
+            .is_void(&self.symbols().func_info(func_id).return_ty);
+        if self.check_flow(body) && !returns_void {
+            self.report(Diagnostic::error(
+                DiagnosticKind::MissingReturn {
+                    func_name: func_token.lexeme.to_owned(),
+                },
+                func_token.span,
+            ));
+        }
+
         self.current_function = prev;
         Ok(())
     }
 
+    // Warns about names in the innermost scope that nothing referenced, main is always used
+    fn report_unused(&self, params: &[SymbolID]) {
+        let symbols = self.symbols();
+        for id in symbols.unused_in_scope() {
+            let name = symbols.symbol_name(id).to_owned();
+            let kind = if symbols.is_func(id) {
+                if name == "main" {
+                    continue;
+                }
+                DiagnosticKind::UnusedFunc {
+                    func_name: name.clone(),
+                }
+            } else if symbols.const_value(id).is_some() {
+                DiagnosticKind::UnusedConst {
+                    const_name: name.clone(),
+                }
+            } else if params.contains(&id) {
+                DiagnosticKind::UnusedParam {
+                    param_name: name.clone(),
+                }
+            } else {
+                DiagnosticKind::UnusedVar {
+                    var_name: name.clone(),
+                }
+            };
+
+            let span = symbols.symbol_span(id).expect("declared names have a span");
+            self.report(Diagnostic::warning(kind, span).with_help(format!(
+                "if this is intentional, prefix it with an underscore: `_{name}`"
+            )));
+        }
+    }
+
+    // Returns whether control can fall off the end of `stmt`, warning about any statement
+    // that can never run along the way
+    fn check_flow(&self, stmt: &Stmt) -> bool {
+        match &stmt.kind {
+            StmtKind::Return(_) | StmtKind::Break(_) | StmtKind::Continue(_) => false,
+            StmtKind::Block(stmts) => self.check_flow_block(stmts),
+            StmtKind::If(info) => {
+                let if_falls = self.check_flow(&info.do_if);
+                match &info.do_else {
+                    Some(do_else) => self.check_flow(do_else) || if_falls,
+                    None => true,
+                }
+            }
+            StmtKind::While(info) => {
+                self.check_flow(&info.body);
+                // Only a `while true` without a break never finishes
+                !matches!(info.cond.kind, ExprKind::Bool(true)) || contains_break(&info.body)
+            }
+            _ => true,
+        }
+    }
+
+    fn check_flow_block(&self, stmts: &[Stmt]) -> bool {
+        let mut diverged_at: Option<&Stmt> = None;
+        for stmt in stmts {
+            if let Some(diverging) = diverged_at {
+                if matches!(stmt.kind, StmtKind::Empty) {
+                    continue;
+                }
+
+                // Only the first unreachable statement is reported, the rest follow from it
+                self.report(
+                    Diagnostic::warning(DiagnosticKind::UnreachableCode, stmt.span)
+                        .with_spanned_note(
+                            "any code following this statement is unreachable",
+                            diverging.span,
+                        ),
+                );
+                return false;
+            }
+
+            if !self.check_flow(stmt) {
+                diverged_at = Some(stmt);
+            }
+        }
+        diverged_at.is_none()
+    }
+
     fn analyze_while(&mut self, info: &mut WhileInfo) -> Result<(), Diagnostic> {
         let WhileInfo { id, cond, body } = info;
 
+        self.analyze_condition(cond)?;
+
         *id = Some(self.next_loop_id.next());
         let prev = self.current_loop.take();
         self.current_loop = *id;
 
-        self.analyze_expr(cond)?;
-        self.analyze_statement(body)?;
-
-        // TODO: Be very careful here, right now there is no problem because on error we fully stop
-        // compiling but if we resync, an error above and this will never pop
+        // Body errors are reported rather than propagated, so the loop is always restored
+        self.analyze_statement_reporting(body);
 
         self.current_loop = prev;
 
@@ -207,10 +758,10 @@ impl<'ctx> SemanticAnalyzer<'ctx> {
         if let Some(current_id) = self.current_loop {
             *id = Some(current_id)
         } else {
-            return Err(Diagnostic {
-                line: token.line,
-                kind: DiagnosticKind::ContinueOutsideLoop,
-            });
+            return Err(Diagnostic::error(
+                DiagnosticKind::ContinueOutsideLoop,
+                token.span,
+            ));
         }
         Ok(())
     }
@@ -219,114 +770,849 @@ impl<'ctx> SemanticAnalyzer<'ctx> {
         if let Some(current_id) = self.current_loop {
             *id = Some(current_id)
         } else {
-            return Err(Diagnostic {
-                line: token.line,
-                kind: DiagnosticKind::ContinueOutsideLoop,
-            });
+            return Err(Diagnostic::error(
+                DiagnosticKind::BreakOutsideLoop,
+                token.span,
+            ));
         }
         Ok(())
     }
 
     fn analyze_return(&mut self, info: &mut ReturnInfo, token: Token) -> Result<(), Diagnostic> {
         let ReturnInfo { id, expr } = info;
-        self.analyze_expr(expr)?;
-        if self.current_function.is_none() {
-            return Err(Diagnostic {
-                line: token.line,
-                kind: DiagnosticKind::ContinueOutsideLoop,
-            });
+        let Some(func_id) = self.current_function else {
+            return Err(Diagnostic::error(
+                DiagnosticKind::ReturnOutsideFunc,
+                token.span,
+            ));
+        };
+        *id = Some(func_id);
+
+        let return_ty = self.symbols().func_info(func_id).return_ty.clone();
+        match expr {
+            Some(expr) => {
+                let expr_ty = self.analyze_expr_hinted(expr, Some(&return_ty))?;
+                self.expect_type(&expr_ty, &return_ty, expr.span);
+            }
+            None => {
+                let void_ty = self.symbols().primitive_type("void");
+                self.expect_type(&void_ty, &return_ty, token.span);
+            }
         }
-        *id = self.current_function;
-        Ok(()) // TODO: Return here when we add more types
+        Ok(())
+    }
+
+    fn analyze_condition(&mut self, cond: &mut Box<Expr>) -> Result<(), Diagnostic> {
+        let cond_ty = self.analyze_expr(cond)?;
+        let bool_ty = self.symbols().primitive_type("bool");
+        self.expect_type(&cond_ty, &bool_ty, cond.span);
+        Ok(())
     }
 
+    fn analyze_expr(&mut self, expr: &mut Box<Expr>) -> Result<ResolvedType, Diagnostic> {
+        self.analyze_expr_hinted(expr, None)
+    }
+
+    // The hint is the type the surrounding code expects, integer literals take it on
+    // instead of defaulting to i64
     // TODO: Restructure this to avoid token cloning
     // instead of passing the data in the matched enum
     // we should match and then pass the whole node into the function ideally
     // I need a way to do this with the borrow checker
-    fn analyze_expr(&mut self, expr: &mut Box<Expr>) -> Result<(), Diagnostic> {
-        match &mut expr.kind {
-            ExprKind::BinOp(info) => self.analyze_expr_binop(info)?,
-            ExprKind::UnOp(info) => self.analyze_expr_unop(info)?,
-            ExprKind::Var(id) => self.analyze_expr_var(id, expr.token.clone())?,
+    fn analyze_expr_hinted(
+        &mut self,
+        expr: &mut Box<Expr>,
+        hint: Option<&ResolvedType>,
+    ) -> Result<ResolvedType, Diagnostic> {
+        let ty = match &mut expr.kind {
+            ExprKind::BinOp(info) => self.analyze_expr_binop(info, hint)?,
+            ExprKind::UnOp(info) => self.analyze_expr_unop(info, hint)?,
+            ExprKind::Var(id) => self.analyze_expr_var(id, expr.token.clone(), true)?,
             ExprKind::Func(info) => self.analyze_expr_func(info, expr.token.clone())?,
-            ExprKind::Literal(num) => self.analyze_expr_literal(num)?,
-        }
-        Ok(())
+            ExprKind::Literal(info) => self.analyze_expr_literal(info, hint, &expr.token)?,
+            ExprKind::Bool(_) => self.symbols().primitive_type("bool"),
+            ExprKind::Char(_) => self.symbols().primitive_type("char"),
+            ExprKind::Str(_) => ResolvedType::Str,
+            ExprKind::Cast(info) => self.analyze_expr_cast(info, expr.span)?,
+            ExprKind::CompoundAssign(info) => self.analyze_expr_compound_assign(info)?,
+            ExprKind::Index(info) => self.analyze_expr_index(info)?,
+            ExprKind::Array(info) => self.analyze_expr_array(info, hint, expr.span)?,
+            ExprKind::ArrayRepeat(info) => self.analyze_expr_array_repeat(info, hint)?,
+            ExprKind::Field(info) => self.analyze_expr_field(info, expr.token.clone())?,
+            ExprKind::StructLit(info) => {
+                self.analyze_expr_struct_lit(info, expr.token.clone(), expr.span)?
+            }
+        };
+        expr.ty = Some(ty.clone());
+        Ok(ty)
     }
 
-    fn analyze_expr_binop(&mut self, info: &mut BinOpInfo) -> Result<(), Diagnostic> {
+    fn analyze_expr_binop(
+        &mut self,
+        info: &mut BinOpInfo,
+        hint: Option<&ResolvedType>,
+    ) -> Result<ResolvedType, Diagnostic> {
         let BinOpInfo { op, lhs, rhs } = info;
-        self.analyze_expr(lhs)?;
-        self.analyze_expr(rhs)?;
 
-        if matches!(op, BinOpKind::Assign) && !matches!(&lhs.kind, ExprKind::Var(_)) {
-            return Err(Diagnostic {
-                line: lhs.token.line,
-                kind: DiagnosticKind::InvalidAssignment,
-            });
+        let is_arith = matches!(
+            op,
+            BinOpKind::Add
+                | BinOpKind::Sub
+                | BinOpKind::Mult
+                | BinOpKind::Div
+                | BinOpKind::Mod
+                | BinOpKind::BitAnd
+                | BinOpKind::BitOr
+                | BinOpKind::BitXor
+        );
+        let is_shift = matches!(op, BinOpKind::Shl | BinOpKind::Shr);
+        let operand_hint = if is_arith || is_shift { hint } else { None };
+
+        // Analyze the typed side first so a literal on the left can take its type from the right
+        let literal_first = !is_shift && is_untyped_literal(lhs) && !is_untyped_literal(rhs);
+        let (lhs_ty, rhs_ty) = if is_shift {
+            // The shift amount can be any integer type, it doesn't have to match the value
+            let lhs_ty = self.analyze_expr_hinted(lhs, operand_hint)?;
+            let rhs_ty = self.analyze_expr(rhs)?;
+            (lhs_ty, rhs_ty)
+        } else if matches!(op, BinOpKind::Assign) {
+            let lhs_ty = self.analyze_assign_target(lhs)?;
+            let rhs_ty = self.analyze_expr_hinted(rhs, Some(&lhs_ty))?;
+            (lhs_ty, rhs_ty)
+        } else if literal_first {
+            let rhs_ty = self.analyze_expr_hinted(rhs, operand_hint)?;
+            let lhs_ty = self.analyze_expr_hinted(lhs, Some(&rhs_ty))?;
+            (lhs_ty, rhs_ty)
+        } else {
+            let lhs_ty = self.analyze_expr_hinted(lhs, operand_hint)?;
+            let rhs_ty = self.analyze_expr_hinted(rhs, Some(&lhs_ty))?;
+            (lhs_ty, rhs_ty)
+        };
+
+        if matches!(op, BinOpKind::Assign) {
+            self.expect_assignable(lhs);
         }
 
-        Ok(())
+        // A void operand is reported once, the error type keeps whatever uses the result quiet
+        for (operand, ty) in [(&lhs, &lhs_ty), (&rhs, &rhs_ty)] {
+            if self.symbols().is_void(ty) {
+                self.report(Diagnostic::error(DiagnosticKind::VoidValue, operand.span));
+                return Ok(self.symbols().error_type());
+            }
+        }
+
+        let bool_ty = self.symbols().primitive_type("bool");
+        let lhs_is_pointer = self.symbols().is_pointer(&lhs_ty);
+        Ok(match op {
+            BinOpKind::Assign => {
+                self.expect_type(&rhs_ty, &lhs_ty, rhs.span);
+                lhs_ty
+            }
+            // Moves the pointer by whole elements
+            BinOpKind::Add | BinOpKind::Sub if lhs_is_pointer => {
+                self.expect_integer(&rhs_ty, rhs.span);
+                lhs_ty
+            }
+            BinOpKind::Add
+            | BinOpKind::Sub
+            | BinOpKind::Mult
+            | BinOpKind::Div
+            | BinOpKind::Mod
+            | BinOpKind::BitAnd
+            | BinOpKind::BitOr
+            | BinOpKind::BitXor => {
+                self.expect_integer(&lhs_ty, lhs.span);
+                self.expect_type(&rhs_ty, &lhs_ty, rhs.span);
+                lhs_ty
+            }
+            BinOpKind::Shl | BinOpKind::Shr => {
+                self.expect_integer(&lhs_ty, lhs.span);
+                self.expect_integer(&rhs_ty, rhs.span);
+                lhs_ty
+            }
+            BinOpKind::LessThan
+            | BinOpKind::LessEq
+            | BinOpKind::GreaterThan
+            | BinOpKind::GreaterEq => {
+                // Characters are ordered by code point
+                if !lhs_is_pointer && !self.symbols().is_char(&lhs_ty) {
+                    self.expect_integer(&lhs_ty, lhs.span);
+                }
+                self.expect_type(&rhs_ty, &lhs_ty, rhs.span);
+                bool_ty
+            }
+            BinOpKind::Equals | BinOpKind::NotEquals => {
+                if self.symbols().is_aggregate(&lhs_ty) {
+                    let ty = self.symbols().type_name(&lhs_ty);
+                    self.report(Diagnostic::error(
+                        DiagnosticKind::NotComparable { ty },
+                        lhs.span,
+                    ));
+                } else {
+                    self.expect_type(&rhs_ty, &lhs_ty, rhs.span);
+                }
+                bool_ty
+            }
+            BinOpKind::And | BinOpKind::Or => {
+                self.expect_type(&lhs_ty, &bool_ty, lhs.span);
+                self.expect_type(&rhs_ty, &bool_ty, rhs.span);
+                bool_ty
+            }
+        })
+    }
+
+    fn analyze_expr_compound_assign(
+        &mut self,
+        info: &mut CompoundAssignInfo,
+    ) -> Result<ResolvedType, Diagnostic> {
+        let CompoundAssignInfo { op, lhs, rhs } = info;
+        let lhs_ty = self.analyze_assign_target(lhs)?;
+        self.expect_assignable(lhs);
+        let moves_pointer =
+            self.symbols().is_pointer(&lhs_ty) && matches!(op, BinOpKind::Add | BinOpKind::Sub);
+        let lhs_is_integer = moves_pointer || self.expect_integer(&lhs_ty, lhs.span);
+
+        if matches!(op, BinOpKind::Shl | BinOpKind::Shr) || moves_pointer {
+            let rhs_ty = self.analyze_expr(rhs)?;
+            self.expect_integer(&rhs_ty, rhs.span);
+        } else {
+            let rhs_ty = self.analyze_expr_hinted(rhs, Some(&lhs_ty))?;
+            // A bad target was already reported, don't complain about the value too
+            if lhs_is_integer {
+                self.expect_type(&rhs_ty, &lhs_ty, rhs.span);
+            }
+        }
+        Ok(lhs_ty)
+    }
+
+    fn analyze_expr_index(&mut self, info: &mut IndexInfo) -> Result<ResolvedType, Diagnostic> {
+        let IndexInfo { base, index } = info;
+        let base_ty = self.analyze_expr(base)?;
+        let index_ty = self.analyze_expr(index)?;
+        self.expect_integer(&index_ty, index.span);
+
+        if self.symbols().is_error_type(&base_ty) {
+            return Ok(base_ty);
+        }
+
+        // Indexing a string gives its bytes. Only literals have a length known up front
+        if self.symbols().is_str(&base_ty) {
+            if let ExprKind::Str(contents) = &base.kind {
+                self.check_const_index(index, contents.len());
+            }
+            return Ok(self.symbols().primitive_type("u8"));
+        }
+
+        let elem = self.symbols().elem_type(&base_ty);
+        let Some((elem_ty, len)) = elem else {
+            let ty = self.symbols().type_name(&base_ty);
+            self.report(Diagnostic::error(
+                DiagnosticKind::NotIndexable { ty },
+                base.span,
+            ));
+            return Ok(self.symbols().error_type());
+        };
+
+        self.check_const_index(index, len);
+        Ok(elem_ty)
     }
 
-    fn analyze_expr_unop(&mut self, info: &mut UnOpInfo) -> Result<(), Diagnostic> {
+    // Indices only known at runtime aren't checked
+    fn check_const_index(&self, index: &Expr, len: usize) {
+        if let Ok(value) = self.eval_const(index)
+            && !(0..len as i64).contains(&value)
+        {
+            self.report(Diagnostic::error(
+                DiagnosticKind::IndexOutOfBounds { index: value, len },
+                index.span,
+            ));
+        }
+    }
+
+    // Elements take their type from the hint when there is one, otherwise from the first element
+    fn analyze_expr_array(
+        &mut self,
+        info: &mut ArrayInfo,
+        hint: Option<&ResolvedType>,
+        span: SourceSpan,
+    ) -> Result<ResolvedType, Diagnostic> {
+        let ArrayInfo { elems, slot } = info;
+        let mut elem_ty = hint
+            .and_then(|hint| self.symbols().elem_type(hint))
+            .map(|(elem_ty, _)| elem_ty);
+
+        for elem in elems.iter_mut() {
+            let ty = self.analyze_expr_hinted(elem, elem_ty.as_ref())?;
+            match &elem_ty {
+                Some(elem_ty) => {
+                    self.expect_type(&ty, elem_ty, elem.span);
+                }
+                None => elem_ty = Some(ty),
+            }
+        }
+
+        let Some(elem_ty) = elem_ty else {
+            self.report(Diagnostic::error(DiagnosticKind::CannotInferArray, span));
+            return Ok(self.symbols().error_type());
+        };
+        let ty = ResolvedType::Array(Box::new(elem_ty), elems.len());
+        *slot = self.reserve_temp(&ty);
+        Ok(ty)
+    }
+
+    fn analyze_expr_array_repeat(
+        &mut self,
+        info: &mut ArrayRepeatInfo,
+        hint: Option<&ResolvedType>,
+    ) -> Result<ResolvedType, Diagnostic> {
+        let ArrayRepeatInfo { value, count, slot } = info;
+        let elem_hint = hint
+            .and_then(|hint| self.symbols().elem_type(hint))
+            .map(|(elem_ty, _)| elem_ty);
+
+        let value_ty = self.analyze_expr_hinted(value, elem_hint.as_ref())?;
+        let elem_ty = match elem_hint {
+            Some(elem_ty) => {
+                self.expect_type(&value_ty, &elem_ty, value.span);
+                elem_ty
+            }
+            None => value_ty,
+        };
+
+        let ty = ResolvedType::Array(Box::new(elem_ty), *count);
+        *slot = self.reserve_temp(&ty);
+        Ok(ty)
+    }
+
+    fn analyze_expr_field(
+        &mut self,
+        info: &mut FieldInfo,
+        token: Token,
+    ) -> Result<ResolvedType, Diagnostic> {
+        let FieldInfo { base, offset } = info;
+        let base_ty = self.analyze_expr(base)?;
+
+        let symbols = self.symbols();
+        if symbols.is_error_type(&base_ty) {
+            return Ok(base_ty);
+        }
+        if symbols.is_str(&base_ty) {
+            return Ok(self.analyze_str_field(offset, token));
+        }
+        if !symbols.is_struct(&base_ty) {
+            self.report(Diagnostic::error(
+                DiagnosticKind::NotAStruct {
+                    ty: symbols.type_name(&base_ty),
+                },
+                base.span,
+            ));
+            return Ok(symbols.error_type());
+        }
+
+        match symbols.field(&base_ty, &token.lexeme) {
+            Some(field) => {
+                *offset = Some(field.offset);
+                Ok(field.ty.clone())
+            }
+            None => {
+                self.report(Diagnostic::error(
+                    DiagnosticKind::UnknownField {
+                        ty: symbols.type_name(&base_ty),
+                        field: token.lexeme.to_owned(),
+                    },
+                    token.span,
+                ));
+                Ok(symbols.error_type())
+            }
+        }
+    }
+
+    // Strings are laid out like `struct { ptr: *u8, len: i64 }`
+    fn analyze_str_field(&self, offset: &mut Option<usize>, token: Token) -> ResolvedType {
+        let symbols = self.symbols();
+        match token.lexeme.as_str() {
+            "ptr" => {
+                *offset = Some(0);
+                ResolvedType::Pointer(Box::new(symbols.primitive_type("u8")))
+            }
+            "len" => {
+                *offset = Some(8);
+                symbols.primitive_type("i64")
+            }
+            _ => {
+                self.report(Diagnostic::error(
+                    DiagnosticKind::UnknownField {
+                        ty: symbols.type_name(&ResolvedType::Str),
+                        field: token.lexeme.to_owned(),
+                    },
+                    token.span,
+                ));
+                symbols.error_type()
+            }
+        }
+    }
+
+    // Every field has to be given exactly once, in any order
+    fn analyze_expr_struct_lit(
+        &mut self,
+        info: &mut StructLitInfo,
+        token: Token,
+        span: SourceSpan,
+    ) -> Result<ResolvedType, Diagnostic> {
+        let StructLitInfo { fields, slot } = info;
+        let resolved = self.symbols().get_type_id(&token);
+        let ty = match resolved {
+            Ok(type_id) => ResolvedType::Named(type_id),
+            Err(diag) => {
+                self.report(diag);
+                self.symbols().error_type()
+            }
+        };
+        if !self.symbols().is_struct(&ty) && !self.symbols().is_error_type(&ty) {
+            let found = self.symbols().type_name(&ty);
+            self.report(Diagnostic::error(
+                DiagnosticKind::NotAStruct { ty: found },
+                token.span,
+            ));
+        }
+        let ty_name = self.symbols().type_name(&ty);
+
+        let mut given: Vec<String> = vec![];
+        for init in fields.iter_mut() {
+            let name = init.token.lexeme.to_owned();
+            let field = self
+                .symbols()
+                .field(&ty, &name)
+                .map(|field| (field.ty.clone(), field.offset));
+            let Some((field_ty, offset)) = field else {
+                if self.symbols().is_struct(&ty) {
+                    self.report(Diagnostic::error(
+                        DiagnosticKind::UnknownField {
+                            ty: ty_name.clone(),
+                            field: name,
+                        },
+                        init.token.span,
+                    ));
+                }
+                self.analyze_expr(&mut init.expr)?;
+                continue;
+            };
+
+            if given.contains(&name) {
+                self.report(Diagnostic::error(
+                    DiagnosticKind::DuplicateField {
+                        field: name.clone(),
+                    },
+                    init.token.span,
+                ));
+            }
+            given.push(name);
+            init.offset = Some(offset);
+
+            let expr_ty = self.analyze_expr_hinted(&mut init.expr, Some(&field_ty))?;
+            self.expect_type(&expr_ty, &field_ty, init.expr.span);
+        }
+
+        let missing: Vec<String> = match self.symbols().struct_info(&ty) {
+            Some(struct_info) => struct_info
+                .fields
+                .iter()
+                .filter(|field| !given.contains(&field.name))
+                .map(|field| format!("'{}'", field.name))
+                .collect(),
+            None => vec![],
+        };
+        if !missing.is_empty() {
+            self.report(Diagnostic::error(
+                DiagnosticKind::MissingFields {
+                    ty: ty_name,
+                    fields: missing.join(", "),
+                },
+                span,
+            ));
+        }
+
+        *slot = self.reserve_temp(&ty);
+        Ok(ty)
+    }
+
+    // Stack space for a value built in place, initializers of globals are never generated.
+    // It's rounded up to whole eightbytes so a struct returned in registers can be stored
+    // straight into it
+    fn reserve_temp(&mut self, ty: &ResolvedType) -> Option<i64> {
+        let func_id = self.current_function?;
+        let size = self.symbols().size_of(ty).next_multiple_of(8);
+        let align = self.symbols().align_of(ty);
+        Some(self.symbols_mut().increase_stack_size(func_id, size, align))
+    }
+
+    fn analyze_expr_unop(
+        &mut self,
+        info: &mut UnOpInfo,
+        hint: Option<&ResolvedType>,
+    ) -> Result<ResolvedType, Diagnostic> {
         let UnOpInfo { op, expr } = info;
-        let _ = op;
-        self.analyze_expr(expr)?;
-        Ok(())
+        // The operand of a pointer operator has a different type than the result
+        let operand_hint = match op {
+            UnOpKind::AddrOf | UnOpKind::Deref => None,
+            _ => hint,
+        };
+        let ty = self.analyze_expr_hinted(expr, operand_hint)?;
+        match op {
+            UnOpKind::Neg => {
+                if !self.symbols().is_signed(&ty) {
+                    let found = self.symbols().type_name(&ty);
+                    self.report(Diagnostic::error(
+                        DiagnosticKind::TypeMismatch {
+                            expected: "signed integer".to_string(),
+                            found,
+                        },
+                        expr.span,
+                    ));
+                }
+                Ok(ty)
+            }
+            UnOpKind::Not => {
+                let bool_ty = self.symbols().primitive_type("bool");
+                self.expect_type(&ty, &bool_ty, expr.span);
+                Ok(bool_ty)
+            }
+            UnOpKind::BitNot => {
+                self.expect_integer(&ty, expr.span);
+                Ok(ty)
+            }
+            UnOpKind::AddrOf => {
+                self.expect_addressable(expr);
+                Ok(ResolvedType::Pointer(Box::new(ty)))
+            }
+            UnOpKind::Deref => {
+                let symbols = self.symbols();
+                if symbols.is_error_type(&ty) {
+                    return Ok(ty);
+                }
+                match symbols.pointee_type(&ty) {
+                    Some(pointee) => Ok(pointee),
+                    None => {
+                        self.report(Diagnostic::error(
+                            DiagnosticKind::NotAPointer {
+                                ty: symbols.type_name(&ty),
+                            },
+                            expr.span,
+                        ));
+                        Ok(symbols.error_type())
+                    }
+                }
+            }
+        }
+    }
+
+    fn analyze_expr_cast(
+        &mut self,
+        info: &mut CastInfo,
+        span: SourceSpan,
+    ) -> Result<ResolvedType, Diagnostic> {
+        let CastInfo { expr, ty } = info;
+        let from = self.analyze_expr(expr)?;
+        let resolved = self.symbols().resolve_type(ty);
+        let to = match resolved {
+            Ok(to) => to,
+            Err(diag) => {
+                self.report(diag);
+                return Ok(self.symbols().error_type());
+            }
+        };
+
+        let symbols = self.symbols();
+        if !symbols.can_cast(&from, &to) {
+            // Wider integers may not be valid code points, so they never convert to char
+            let diag = if symbols.is_char(&to) && symbols.is_integer(&from) {
+                Diagnostic::error(
+                    DiagnosticKind::InvalidCharCast {
+                        from: symbols.type_name(&from),
+                    },
+                    span,
+                )
+                .with_help("cast to `u8` first if the value fits in a byte")
+            } else {
+                Diagnostic::error(
+                    DiagnosticKind::InvalidCast {
+                        from: symbols.type_name(&from),
+                        to: symbols.type_name(&to),
+                    },
+                    span,
+                )
+            };
+            self.report(diag);
+        }
+        Ok(to)
     }
 
     fn analyze_expr_var(
         &mut self,
         id: &mut Option<SymbolID>,
         token: Token,
-    ) -> Result<(), Diagnostic> {
-        *id = Some(self.symbols().get_var_id(&token)?);
-        Ok(())
+        read: bool,
+    ) -> Result<ResolvedType, Diagnostic> {
+        let resolved = if read {
+            self.symbols_mut().get_var_id(&token)
+        } else {
+            self.symbols().lookup_var_id(&token)
+        };
+        let var_id = match resolved {
+            Ok(var_id) => var_id,
+            Err(diag) => {
+                self.report(diag);
+                self.symbols_mut().register_poison(&token)
+            }
+        };
+        *id = Some(var_id);
+        Ok(self.symbols().var_type(var_id))
+    }
+
+    // Assigning to a variable doesn't read it, so it doesn't count as a use. Writing into an
+    // element or field still does, like in Rust
+    fn analyze_assign_target(
+        &mut self,
+        target: &mut Box<Expr>,
+    ) -> Result<ResolvedType, Diagnostic> {
+        let ExprKind::Var(id) = &mut target.kind else {
+            return self.analyze_expr(target);
+        };
+        let ty = self.analyze_expr_var(id, target.token.clone(), false)?;
+        target.ty = Some(ty.clone());
+        Ok(ty)
     }
 
     fn analyze_expr_func(
         &mut self,
         info: &mut FuncCallInfo,
         token: Token,
-    ) -> Result<(), Diagnostic> {
-        let FuncCallInfo { id, args } = info;
-        *id = Some(self.symbols().get_func_id(&token)?);
+    ) -> Result<ResolvedType, Diagnostic> {
+        let FuncCallInfo { id, args, slot } = info;
+        let resolved = self.symbols_mut().get_func_id(&token);
+        let func_id = match resolved {
+            Ok(func_id) => func_id,
+            Err(diag) => {
+                self.report(diag);
+                self.symbols_mut().register_poison(&token)
+            }
+        };
+        *id = Some(func_id);
+
+        let (param_tys, return_ty) = if self.symbols().is_poison(func_id) {
+            (vec![], self.symbols().error_type())
+        } else {
+            let symbols = self.symbols();
+            let func_info = symbols.func_info(func_id);
+            (func_info.param_tys.clone(), func_info.return_ty.clone())
+        };
 
-        for arg in args.iter_mut() {
-            self.analyze_expr(arg)?;
+        let mut arg_tys = vec![];
+        for (index, arg) in args.iter_mut().enumerate() {
+            arg_tys.push(self.analyze_expr_hinted(arg, param_tys.get(index))?);
         }
 
-        let expected_num = self.symbols().func_info(id.unwrap()).params.len();
-        if args.len() != expected_num {
-            return Err(Diagnostic {
-                line: token.line,
-                kind: DiagnosticKind::MismatchedArgLen {
+        if self.symbols().is_poison(func_id) {
+            return Ok(return_ty);
+        }
+
+        // Structs and strings are held by address, so a returned one needs somewhere to live
+        if self.symbols().is_aggregate(&return_ty) {
+            *slot = self.reserve_temp(&return_ty);
+        }
+
+        if args.len() != param_tys.len() {
+            self.report(Diagnostic::error(
+                DiagnosticKind::MismatchedArgLen {
                     found_num: args.len(),
-                    expected_num,
+                    expected_num: param_tys.len(),
                 },
-            });
+                token.span,
+            ));
         }
 
-        Ok(())
+        for ((arg, arg_ty), param_ty) in args.iter().zip(&arg_tys).zip(&param_tys) {
+            self.expect_type(arg_ty, param_ty, arg.span);
+        }
+
+        Ok(return_ty)
     }
 
-    fn analyze_expr_literal(&mut self, _num: &mut i64) -> Result<(), Diagnostic> {
-        Ok(())
+    // A suffix fixes the type, otherwise it comes from the hint
+    fn analyze_expr_literal(
+        &mut self,
+        info: &LiteralInfo,
+        hint: Option<&ResolvedType>,
+        token: &Token,
+    ) -> Result<ResolvedType, Diagnostic> {
+        let symbols = self.symbols();
+        let ty = match (&info.suffix, hint) {
+            (Some(suffix), _) => symbols.primitive_type(suffix),
+            (None, Some(hint)) if symbols.is_integer(hint) && !symbols.is_error_type(hint) => {
+                hint.clone()
+            }
+            _ => symbols.primitive_type("i64"),
+        };
+
+        let (min, max) = symbols.int_range(&ty);
+        if !(min..=max).contains(&info.value) {
+            self.report(Diagnostic::error(
+                DiagnosticKind::IntLiteralOutOfRange {
+                    literal: token.lexeme.to_owned(),
+                    ty: symbols.type_name(&ty),
+                },
+                token.span,
+            ));
+        }
+
+        Ok(ty)
+    }
+
+    // Reports a mismatch but lets analysis carry on, the caller decides what type to continue with
+    fn expect_type(&self, found: &ResolvedType, expected: &ResolvedType, span: SourceSpan) -> bool {
+        let symbols = self.symbols();
+        if symbols.types_compatible(found, expected) {
+            return true;
+        }
+
+        let expected_name = symbols.type_name(expected);
+        let mut diag = Diagnostic::error(
+            DiagnosticKind::TypeMismatch {
+                expected: expected_name.clone(),
+                found: symbols.type_name(found),
+            },
+            span,
+        );
+        // Numbers are never converted implicitly, point at the explicit way to do it
+        if symbols.can_cast(found, expected) {
+            diag = diag.with_help(format!("use an explicit cast: `as {expected_name}`"));
+        }
+        self.report(diag);
+        false
+    }
+
+    fn expect_non_void_var(&self, ty: &ResolvedType, var_token: &Token) -> bool {
+        if !self.symbols().is_void(ty) {
+            return true;
+        }
+
+        self.report(Diagnostic::error(
+            DiagnosticKind::VoidVariable {
+                var_name: var_token.lexeme.to_owned(),
+            },
+            var_token.span,
+        ));
+        false
+    }
+
+    fn expect_assignable(&self, expr: &Expr) -> bool {
+        if let ExprKind::Index(IndexInfo { base, .. }) | ExprKind::Field(FieldInfo { base, .. }) =
+            &expr.kind
+        {
+            // Literals live in read only memory, so no string is changed in place
+            if base.ty.as_ref().is_some_and(|ty| self.symbols().is_str(ty)) {
+                self.report(Diagnostic::error(DiagnosticKind::StrImmutable, expr.span));
+                return false;
+            }
+            return self.expect_assignable(base);
+        }
+
+        // Whatever a pointer points to can be written through it
+        if let ExprKind::UnOp(UnOpInfo {
+            op: UnOpKind::Deref,
+            ..
+        }) = expr.kind
+        {
+            return true;
+        }
+
+        if let ExprKind::Var(Some(id)) = expr.kind {
+            let symbols = self.symbols();
+            if symbols.const_value(id).is_none() {
+                return true;
+            }
+
+            self.report(Diagnostic::error(
+                DiagnosticKind::AssignToConst {
+                    const_name: symbols.symbol_name(id).to_owned(),
+                },
+                expr.span,
+            ));
+            return false;
+        }
+
+        self.report(Diagnostic::error(
+            DiagnosticKind::InvalidAssignment,
+            expr.span,
+        ));
+        false
+    }
+
+    // Only values that live in memory have an address, constants are inlined where they're used
+    fn expect_addressable(&self, expr: &Expr) -> bool {
+        match &expr.kind {
+            ExprKind::Index(IndexInfo { base, .. }) | ExprKind::Field(FieldInfo { base, .. }) => {
+                // A pointer into a string could be written through, but its bytes are read only
+                if base.ty.as_ref().is_some_and(|ty| self.symbols().is_str(ty)) {
+                    self.report(Diagnostic::error(DiagnosticKind::StrImmutable, expr.span));
+                    return false;
+                }
+                self.expect_addressable(base)
+            }
+            ExprKind::UnOp(UnOpInfo {
+                op: UnOpKind::Deref,
+                ..
+            }) => true,
+            ExprKind::Var(Some(id)) => {
+                let symbols = self.symbols();
+                if symbols.const_value(*id).is_none() {
+                    return true;
+                }
+
+                self.report(Diagnostic::error(
+                    DiagnosticKind::AddressOfConst {
+                        const_name: symbols.symbol_name(*id).to_owned(),
+                    },
+                    expr.span,
+                ));
+                false
+            }
+            _ => {
+                self.report(Diagnostic::error(DiagnosticKind::NotAddressable, expr.span));
+                false
+            }
+        }
+    }
+
+    fn expect_integer(&self, found: &ResolvedType, span: SourceSpan) -> bool {
+        let symbols = self.symbols();
+        if symbols.is_integer(found) {
+            return true;
+        }
+
+        self.report(Diagnostic::error(
+            DiagnosticKind::TypeMismatch {
+                expected: "integer".to_string(),
+                found: symbols.type_name(found),
+            },
+            span,
+        ));
+        false
     }
 
     fn validate_main(&mut self) -> Result<(), Diagnostic> {
-        let id = self.symbols().get_main_id();
-        match id {
-            Some(id) if self.symbols().func_info(id).params.len() == 0 => Ok(()),
-            _ => Err(Diagnostic {
-                line: -1,
-                kind: DiagnosticKind::InvalidMain,
-            }),
+        let symbols = self.symbols();
+        let i64_ty = symbols.primitive_type("i64");
+        match symbols.get_main_id().map(|id| symbols.func_info(id)) {
+            Some(info) if info.param_tys.is_empty() && info.return_ty == i64_ty => Ok(()),
+            _ => Err(Diagnostic::unspanned(DiagnosticKind::InvalidMain)),
         }
     }
 
+    fn report(&self, diag: Diagnostic) {
+        self.ctx.diags.borrow_mut().report(diag);
+    }
+
     fn symbols_mut(&mut self) -> RefMut<'ctx, Symbols> {
         self.ctx.symbols.borrow_mut()
     }
@@ -335,3 +1621,23 @@ impl<'ctx> SemanticAnalyzer<'ctx> {
         self.ctx.symbols.borrow()
     }
 }
+
+// Breaks inside a nested loop only leave that loop, so they aren't looked for
+fn contains_break(stmt: &Stmt) -> bool {
+    match &stmt.kind {
+        StmtKind::Break(_) => true,
+        StmtKind::Block(stmts) => stmts.iter().any(contains_break),
+        StmtKind::If(info) => {
+            contains_break(&info.do_if) || info.do_else.as_deref().is_some_and(contains_break)
+        }
+        _ => false,
+    }
+}
+
+// Integer literals without a type of their own, which adapt to whatever they are used with
+fn is_untyped_literal(expr: &Expr) -> bool {
+    matches!(
+        &expr.kind,
+        ExprKind::Literal(LiteralInfo { suffix: None, .. })
+    )
+}
@@ -14,6 +14,23 @@ impl Source {
     pub fn get_spanned(&self, span: &SourceSpan) -> &str {
         &self.src[span.low..span.high]
     }
+
+    // 1-based line and (char) column of a byte offset
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let before = &self.src[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |pos| pos + 1);
+        (line, before[line_start..].chars().count() + 1)
+    }
+
+    // Text of a 1-based line without its line terminator
+    pub fn line_text(&self, line: usize) -> &str {
+        self.src
+            .split('\n')
+            .nth(line - 1)
+            .unwrap_or("")
+            .trim_end_matches('\r')
+    }
 }
 
 impl Deref for Source {
@@ -1,6 +1,7 @@
+use crate::abi::{self, ArgLoc};
 use crate::diagnostic::{Diagnostic, DiagnosticKind};
 use crate::parser::ParsedType;
-use crate::tokens::Token;
+use crate::tokens::{SourceSpan, Token};
 use std::collections::HashMap;
 use std::ops::Deref;
 
@@ -16,29 +17,107 @@ impl Deref for SymbolID {
 }
 
 // May seem bare-bones or unnecessary now but its future proofing
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum GenericType<T> {
     Named(T),
+    Array(Box<GenericType<T>>, usize), // [T; N]
+    Pointer(Box<GenericType<T>>),      // *T
+    Str,                               // Pointer to the bytes followed by their length
+}
+
+pub type ResolvedType = GenericType<SymbolID>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrimitiveKind {
+    Integer { size: usize, signed: bool },
+    Bool,
+    // A unicode scalar value, held as its u32 code point
+    Char,
+    // Result of functions without a meaningful return value, never held by a variable
+    Void,
+    // Given to anything whose type could not be determined, compatible with every type
+    // so one mistake doesn't cascade into a pile of mismatches
+    Error,
 }
 
-type ResolvedType = GenericType<SymbolID>;
+#[derive(Debug)]
+pub struct FieldDef {
+    pub name: String,
+    pub ty: ResolvedType,
+    pub offset: usize,
+}
+
+#[derive(Debug)]
+pub struct StructInfo {
+    pub fields: Vec<FieldDef>,
+    pub size: usize,
+    pub align: usize,
+}
 
 #[derive(Debug)]
 pub enum TypeDefInfo {
-    Primative,
+    Primative(PrimitiveKind),
+    // Structs are declared before their fields are laid out, so they can refer to each other
+    // in any order. None until then
+    Struct(Option<StructInfo>),
+}
+
+// Where a variable's value lives
+#[derive(Debug, Clone)]
+pub enum Storage {
+    Stack(i64),         // Offset from %rbp
+    Global(StaticData), // Static data, holding its initial value
+    Const(i64),         // Known at compile time and inlined at every use
+}
+
+// Initial value of a global. Where the bytes of a string end up is only known to the assembler,
+// so pointers to them are left zeroed in `bytes` and filled in through relocations
+#[derive(Debug, Clone)]
+pub struct StaticData {
+    pub bytes: Vec<u8>,
+    pub strings: Vec<(usize, String)>, // Offset of each pointer to string bytes and the string
+}
+
+impl StaticData {
+    pub fn zeroed(size: usize) -> Self {
+        StaticData {
+            bytes: vec![0; size],
+            strings: vec![],
+        }
+    }
+
+    pub fn is_zeroed(&self) -> bool {
+        self.strings.is_empty() && self.bytes.iter().all(|&byte| byte == 0)
+    }
+
+    // Places `data` at `offset`, growing to fit it
+    pub fn write(&mut self, offset: usize, data: StaticData) {
+        let end = offset + data.bytes.len();
+        if self.bytes.len() < end {
+            self.bytes.resize(end, 0);
+        }
+        self.bytes[offset..end].copy_from_slice(&data.bytes);
+        self.strings.extend(
+            data.strings
+                .into_iter()
+                .map(|(at, contents)| (offset + at, contents)),
+        );
+    }
 }
 
 #[derive(Debug)]
 pub struct VarInfo {
-    pub _ty: ResolvedType,
-    pub offset: i64,
+    pub ty: ResolvedType,
+    pub storage: Storage,
 }
 
 #[derive(Debug)]
 pub struct FuncInfo {
-    pub _return_ty: ResolvedType,
-    pub params: Vec<SymbolID>,
+    pub return_ty: ResolvedType,
+    pub param_tys: Vec<ResolvedType>, // Known as soon as the function is declared
+    pub params: Vec<SymbolID>,        // Filled in once the body is analyzed
     pub stack_size: usize,
+    pub return_slot: Option<i64>, // Where the pointer for a struct returned in memory is kept
 }
 
 #[derive(Debug)]
@@ -46,12 +125,16 @@ pub enum SymbolKind {
     Var(VarInfo),
     Func(FuncInfo),
     Type(TypeDefInfo),
+    // Stands in for a name that failed to resolve, so it is only reported once
+    Poison,
 }
 
 #[derive(Debug)]
 pub struct SymbolInfo {
-    line: i32,
+    name: String,
+    span: Option<SourceSpan>,
     kind: SymbolKind,
+    uses: usize, // Number of times the name was resolved, for unused warnings
 }
 
 #[derive(Debug)]
@@ -60,6 +143,12 @@ pub struct Symbols {
     symbols: Vec<SymbolInfo>,
 }
 
+impl Default for Symbols {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Symbols {
     pub fn new() -> Self {
         let mut symbols = Self {
@@ -68,7 +157,22 @@ impl Symbols {
         };
 
         symbols.push_scope();
-        symbols.register_primative("i64");
+        for (name, size, signed) in [
+            ("i8", 1, true),
+            ("i16", 2, true),
+            ("i32", 4, true),
+            ("i64", 8, true),
+            ("u8", 1, false),
+            ("u16", 2, false),
+            ("u32", 4, false),
+            ("u64", 8, false),
+        ] {
+            symbols.register_primative(name, PrimitiveKind::Integer { size, signed });
+        }
+        symbols.register_primative("bool", PrimitiveKind::Bool);
+        symbols.register_primative("char", PrimitiveKind::Char);
+        symbols.register_primative("void", PrimitiveKind::Void);
+        symbols.register_primative("{error}", PrimitiveKind::Error);
 
         symbols
     }
@@ -90,112 +194,467 @@ impl Symbols {
         ty: &ParsedType,
         func_id: SymbolID,
     ) -> Result<SymbolID, Diagnostic> {
-        let ParsedType::Named(type_token) = ty;
-        let type_id = self.get_type_id(type_token)?;
+        let ty = self.resolve_type(ty)?;
+        self.register_local(var_token, ty, func_id)
+    }
 
-        let offset = self.increase_stack_size(func_id, 8);
+    fn register_local(
+        &mut self,
+        var_token: &Token,
+        ty: ResolvedType,
+        func_id: SymbolID,
+    ) -> Result<SymbolID, Diagnostic> {
+        let offset = self.increase_stack_size(func_id, self.size_of(&ty), self.align_of(&ty));
+        self.register_static(var_token, ty, Storage::Stack(offset))
+    }
+
+    // Registers a variable whose storage is already decided, such as globals and constants
+    pub fn register_static(
+        &mut self,
+        var_token: &Token,
+        ty: ResolvedType,
+        storage: Storage,
+    ) -> Result<SymbolID, Diagnostic> {
         let symbol = self.add_symbol(
-            &var_token,
+            var_token,
             SymbolInfo {
-                line: var_token.line,
-                kind: SymbolKind::Var(VarInfo {
-                    _ty: ResolvedType::Named(type_id),
-                    offset,
-                }),
+                name: var_token.lexeme.to_owned(),
+                span: Some(var_token.span),
+                kind: SymbolKind::Var(VarInfo { ty, storage }),
+                uses: 0,
             },
         )?;
 
         Ok(symbol)
     }
 
+    // Parameters passed in registers get a slot in the frame to be stored into, whole
+    // eightbytes at a time. Stack parameters stay where the caller put them, above %rbp
     pub fn register_param(
         &mut self,
         var_token: &Token,
-        ty: &ParsedType,
+        ty: ResolvedType,
         func_id: SymbolID,
     ) -> Result<SymbolID, Diagnostic> {
-        let ParsedType::Named(type_token) = ty;
-        let type_id = self.get_type_id(type_token)?;
-
-        let current_param_num = self.func_info_mut(func_id).params.len();
-
-        const MAX_REGISTER_PARAMS: usize = 6;
-        let symbol = if current_param_num < MAX_REGISTER_PARAMS {
-            self.register_var(var_token, ty, func_id)?
-        } else {
-            let offset = (current_param_num - MAX_REGISTER_PARAMS) as i64 * 8 + 16;
-            self.add_symbol(
-                &var_token,
-                SymbolInfo {
-                    line: var_token.line,
-                    kind: SymbolKind::Var(VarInfo {
-                        _ty: ResolvedType::Named(type_id),
-                        offset,
-                    }),
-                },
-            )?
+        let func_info = self.func_info(func_id);
+        let (locs, _) = abi::arg_locations(self, &func_info.param_tys, &func_info.return_ty);
+
+        let symbol = match locs[func_info.params.len()] {
+            ArgLoc::Regs(_) => {
+                let size = self.size_of(&ty).next_multiple_of(8);
+                let offset = self.increase_stack_size(func_id, size, self.align_of(&ty));
+                self.register_static(var_token, ty, Storage::Stack(offset))?
+            }
+            ArgLoc::Stack(offset) => {
+                self.register_static(var_token, ty, Storage::Stack(offset as i64 + 16))?
+            }
         };
 
         self.func_info_mut(func_id).params.push(symbol);
         Ok(symbol)
     }
 
+    pub fn reserve_return_slot(&mut self, func_id: SymbolID) -> i64 {
+        let slot = self.increase_stack_size(func_id, 8, 8);
+        self.func_info_mut(func_id).return_slot = Some(slot);
+        slot
+    }
+
+    pub fn register_struct(&mut self, struct_token: &Token) -> Result<SymbolID, Diagnostic> {
+        self.add_symbol(
+            struct_token,
+            SymbolInfo {
+                name: struct_token.lexeme.to_owned(),
+                span: Some(struct_token.span),
+                kind: SymbolKind::Type(TypeDefInfo::Struct(None)),
+                uses: 0,
+            },
+        )
+    }
+
+    // Fields are placed in declaration order, each at the next offset aligned for it
+    pub fn define_struct(&mut self, id: SymbolID, fields: Vec<(String, ResolvedType)>) {
+        let mut size: usize = 0;
+        let mut align: usize = 1;
+        let fields = fields
+            .into_iter()
+            .map(|(name, ty)| {
+                let field_align = self.align_of(&ty);
+                let offset = size.next_multiple_of(field_align);
+                size = offset + self.size_of(&ty);
+                align = align.max(field_align);
+                FieldDef { name, ty, offset }
+            })
+            .collect();
+
+        let info = StructInfo {
+            fields,
+            size: size.next_multiple_of(align),
+            align,
+        };
+        self.symbols[*id].kind = SymbolKind::Type(TypeDefInfo::Struct(Some(info)));
+    }
+
     pub fn register_func(
         &mut self,
         func_token: &Token,
-        ty: &ParsedType,
+        return_ty: ResolvedType,
+        param_tys: Vec<ResolvedType>,
     ) -> Result<SymbolID, Diagnostic> {
-        let ParsedType::Named(type_token) = ty;
-        let return_id = self.get_type_id(type_token)?;
-
         let symbol = self.add_symbol(
-            &func_token,
+            func_token,
             SymbolInfo {
-                line: func_token.line,
+                name: func_token.lexeme.to_owned(),
+                span: Some(func_token.span),
                 kind: SymbolKind::Func(FuncInfo {
-                    _return_ty: ResolvedType::Named(return_id),
+                    return_ty,
+                    param_tys,
                     params: vec![],
                     stack_size: 0,
+                    return_slot: None,
                 }),
+                uses: 0,
             },
         )?;
 
-        return Ok(symbol);
+        Ok(symbol)
+    }
+
+    // Resolves a variable that is read, counting it as used
+    pub fn get_var_id(&mut self, var_token: &Token) -> Result<SymbolID, Diagnostic> {
+        let id = self.lookup_var_id(var_token)?;
+        self.symbols[*id].uses += 1;
+        Ok(id)
     }
 
-    pub fn get_var_id(&self, var_token: &Token) -> Result<SymbolID, Diagnostic> {
+    // Resolves a variable without counting a use, for one that is only written to
+    pub fn lookup_var_id(&self, var_token: &Token) -> Result<SymbolID, Diagnostic> {
         match self.get_symbol(&var_token.lexeme) {
-            Some((id, info)) if matches!(info.kind, SymbolKind::Var(_)) => Ok(id),
-            _ => Err(Diagnostic {
-                line: var_token.line,
-                kind: DiagnosticKind::VarUnknown {
+            Some((id, info)) if matches!(info.kind, SymbolKind::Var(_) | SymbolKind::Poison) => {
+                Ok(id)
+            }
+            _ => Err(Diagnostic::error(
+                DiagnosticKind::VarUnknown {
                     var_name: var_token.lexeme.to_owned(),
                 },
-            }),
+                var_token.span,
+            )),
         }
     }
 
     pub fn get_type_id(&self, type_token: &Token) -> Result<SymbolID, Diagnostic> {
         match self.get_symbol(&type_token.lexeme) {
-            Some((id, info)) if matches!(info.kind, SymbolKind::Type(_)) => Ok(id),
-            _ => Err(Diagnostic {
-                line: type_token.line,
-                kind: DiagnosticKind::TypeUnknown {
+            Some((id, info)) if matches!(info.kind, SymbolKind::Type(_) | SymbolKind::Poison) => {
+                Ok(id)
+            }
+            _ => Err(Diagnostic::error(
+                DiagnosticKind::TypeUnknown {
                     type_name: type_token.lexeme.to_owned(),
                 },
-            }),
+                type_token.span,
+            )),
         }
     }
 
-    pub fn get_func_id(&self, func_token: &Token) -> Result<SymbolID, Diagnostic> {
+    pub fn get_func_id(&mut self, func_token: &Token) -> Result<SymbolID, Diagnostic> {
         match self.get_symbol(&func_token.lexeme) {
-            Some((id, info)) if matches!(info.kind, SymbolKind::Func(_)) => Ok(id),
-            _ => Err(Diagnostic {
-                line: func_token.line,
-                kind: DiagnosticKind::FuncUnknown {
+            Some((id, info)) if matches!(info.kind, SymbolKind::Func(_) | SymbolKind::Poison) => {
+                self.symbols[*id].uses += 1;
+                Ok(id)
+            }
+            _ => Err(Diagnostic::error(
+                DiagnosticKind::FuncUnknown {
                     func_name: func_token.lexeme.to_owned(),
                 },
-            }),
+                func_token.span,
+            )),
+        }
+    }
+
+    pub fn register_poison(&mut self, token: &Token) -> SymbolID {
+        let symbol = self.make_symbol_id();
+        self.current_scope_mut()
+            .insert(token.lexeme.to_owned(), symbol);
+        self.symbols.push(SymbolInfo {
+            name: token.lexeme.to_owned(),
+            span: Some(token.span),
+            kind: SymbolKind::Poison,
+            uses: 0,
+        });
+        symbol
+    }
+
+    // Variables and functions declared in the innermost scope that were never referenced,
+    // in declaration order. Names starting with `_` are left out on purpose
+    pub fn unused_in_scope(&self) -> Vec<SymbolID> {
+        let mut unused: Vec<SymbolID> = self
+            .current_scope()
+            .values()
+            .copied()
+            .filter(|id| {
+                let info = &self.symbols[**id];
+                matches!(info.kind, SymbolKind::Var(_) | SymbolKind::Func(_))
+                    && info.uses == 0
+                    && !info.name.starts_with('_')
+            })
+            .collect();
+        unused.sort_by_key(|id| **id);
+        unused
+    }
+
+    pub fn symbol_name(&self, id: SymbolID) -> &str {
+        &self.symbols[*id].name
+    }
+
+    pub fn symbol_span(&self, id: SymbolID) -> Option<SourceSpan> {
+        self.symbols[*id].span
+    }
+
+    pub fn const_value(&self, id: SymbolID) -> Option<i64> {
+        match &self.symbols[*id].kind {
+            SymbolKind::Var(VarInfo {
+                storage: Storage::Const(value),
+                ..
+            }) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn is_func(&self, id: SymbolID) -> bool {
+        matches!(self.symbols[*id].kind, SymbolKind::Func(_))
+    }
+
+    pub fn is_poison(&self, id: SymbolID) -> bool {
+        matches!(self.symbols[*id].kind, SymbolKind::Poison)
+    }
+
+    // Type of a variable, poisoned names are given the error type
+    pub fn var_type(&self, id: SymbolID) -> ResolvedType {
+        match &self.symbols[*id].kind {
+            SymbolKind::Var(info) => info.ty.clone(),
+            _ => self.error_type(),
+        }
+    }
+
+    pub fn primitive_type(&self, name: &str) -> ResolvedType {
+        let id = self.scopes[0]
+            .get(name)
+            .copied()
+            .expect("primitive types are registered in the global scope");
+        ResolvedType::Named(id)
+    }
+
+    pub fn error_type(&self) -> ResolvedType {
+        self.primitive_type("{error}")
+    }
+
+    pub fn primitive_kind(&self, ty: &ResolvedType) -> Option<PrimitiveKind> {
+        let ResolvedType::Named(id) = ty else {
+            return None;
+        };
+        match &self.symbols[**id].kind {
+            SymbolKind::Type(TypeDefInfo::Primative(kind)) => Some(*kind),
+            SymbolKind::Poison => Some(PrimitiveKind::Error),
+            _ => None,
+        }
+    }
+
+    pub fn is_error_type(&self, ty: &ResolvedType) -> bool {
+        self.primitive_kind(ty) == Some(PrimitiveKind::Error)
+    }
+
+    pub fn is_bool(&self, ty: &ResolvedType) -> bool {
+        matches!(
+            self.primitive_kind(ty),
+            Some(PrimitiveKind::Bool | PrimitiveKind::Error)
+        )
+    }
+
+    pub fn is_char(&self, ty: &ResolvedType) -> bool {
+        self.primitive_kind(ty) == Some(PrimitiveKind::Char)
+    }
+
+    pub fn is_void(&self, ty: &ResolvedType) -> bool {
+        self.primitive_kind(ty) == Some(PrimitiveKind::Void)
+    }
+
+    pub fn is_integer(&self, ty: &ResolvedType) -> bool {
+        matches!(
+            self.primitive_kind(ty),
+            Some(PrimitiveKind::Integer { .. } | PrimitiveKind::Error)
+        )
+    }
+
+    pub fn is_signed(&self, ty: &ResolvedType) -> bool {
+        matches!(
+            self.primitive_kind(ty),
+            Some(PrimitiveKind::Integer { signed: true, .. } | PrimitiveKind::Error)
+        )
+    }
+
+    // Inclusive range of values an integer type can hold
+    pub fn int_range(&self, ty: &ResolvedType) -> (i128, i128) {
+        match self.primitive_kind(ty) {
+            Some(PrimitiveKind::Integer { size, signed: true }) => {
+                let bits = size * 8;
+                (-(1 << (bits - 1)), (1 << (bits - 1)) - 1)
+            }
+            Some(PrimitiveKind::Integer {
+                size,
+                signed: false,
+            }) => (0, (1 << (size * 8)) - 1),
+            _ => (i128::MIN, i128::MAX),
+        }
+    }
+
+    pub fn resolve_type(&self, ty: &ParsedType) -> Result<ResolvedType, Diagnostic> {
+        Ok(match ty {
+            ParsedType::Named(type_token) => ResolvedType::Named(self.get_type_id(type_token)?),
+            ParsedType::Array(elem, len) => {
+                ResolvedType::Array(Box::new(self.resolve_type(elem)?), *len)
+            }
+            ParsedType::Pointer(pointee) => {
+                ResolvedType::Pointer(Box::new(self.resolve_type(pointee)?))
+            }
+            ParsedType::Str => ResolvedType::Str,
+        })
+    }
+
+    // Aggregates are held by address rather than by value while being worked on
+    pub fn is_aggregate(&self, ty: &ResolvedType) -> bool {
+        matches!(ty, ResolvedType::Array(..) | ResolvedType::Str) || self.is_struct(ty)
+    }
+
+    pub fn is_str(&self, ty: &ResolvedType) -> bool {
+        matches!(ty, ResolvedType::Str)
+    }
+
+    pub fn is_struct(&self, ty: &ResolvedType) -> bool {
+        matches!(
+            ty,
+            ResolvedType::Named(id)
+                if matches!(self.symbols[**id].kind, SymbolKind::Type(TypeDefInfo::Struct(_)))
+        )
+    }
+
+    // Layout of a struct type, None for other types and structs not laid out yet
+    pub fn struct_info(&self, ty: &ResolvedType) -> Option<&StructInfo> {
+        let ResolvedType::Named(id) = ty else {
+            return None;
+        };
+        match &self.symbols[**id].kind {
+            SymbolKind::Type(TypeDefInfo::Struct(info)) => info.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn field(&self, ty: &ResolvedType, name: &str) -> Option<&FieldDef> {
+        self.struct_info(ty)?
+            .fields
+            .iter()
+            .find(|field| field.name == name)
+    }
+
+    pub fn elem_type(&self, ty: &ResolvedType) -> Option<(ResolvedType, usize)> {
+        match ty {
+            ResolvedType::Array(elem, len) => Some((*elem.clone(), *len)),
+            _ => None,
+        }
+    }
+
+    pub fn pointee_type(&self, ty: &ResolvedType) -> Option<ResolvedType> {
+        match ty {
+            ResolvedType::Pointer(pointee) => Some(*pointee.clone()),
+            _ => None,
+        }
+    }
+
+    // Truncates a value to the width of an integer type, extending it back like a load would
+    pub fn wrap_to_type(&self, value: i64, ty: &ResolvedType) -> i64 {
+        match self.primitive_kind(ty) {
+            Some(PrimitiveKind::Integer { size, signed }) if size < 8 => {
+                let shift = 64 - size as u32 * 8;
+                if signed {
+                    (value << shift) >> shift
+                } else {
+                    ((value as u64) << shift >> shift) as i64
+                }
+            }
+            Some(PrimitiveKind::Char) => value as u32 as i64,
+            _ => value,
+        }
+    }
+
+    pub fn size_of(&self, ty: &ResolvedType) -> usize {
+        match ty {
+            ResolvedType::Array(elem, len) => return self.size_of(elem) * len,
+            ResolvedType::Pointer(_) => return 8,
+            ResolvedType::Str => return 16,
+            ResolvedType::Named(_) => {}
+        }
+        if let Some(info) = self.struct_info(ty) {
+            return info.size;
+        }
+        match self.primitive_kind(ty) {
+            Some(PrimitiveKind::Integer { size, .. }) => size,
+            Some(PrimitiveKind::Bool) => 1,
+            Some(PrimitiveKind::Char) => 4,
+            Some(PrimitiveKind::Void) => 0,
+            Some(PrimitiveKind::Error) | None => 8,
+        }
+    }
+
+    pub fn align_of(&self, ty: &ResolvedType) -> usize {
+        match ty {
+            ResolvedType::Array(elem, _) => self.align_of(elem),
+            ResolvedType::Pointer(_) | ResolvedType::Str => 8,
+            ResolvedType::Named(_) => match self.struct_info(ty) {
+                Some(info) => info.align,
+                None => self.size_of(ty).max(1),
+            },
+        }
+    }
+
+    // The error type is compatible with everything
+    pub fn types_compatible(&self, a: &ResolvedType, b: &ResolvedType) -> bool {
+        match (a, b) {
+            (ResolvedType::Array(a_elem, a_len), ResolvedType::Array(b_elem, b_len)) => {
+                a_len == b_len && self.types_compatible(a_elem, b_elem)
+            }
+            (ResolvedType::Pointer(a_pointee), ResolvedType::Pointer(b_pointee)) => {
+                self.types_compatible(a_pointee, b_pointee)
+            }
+            _ => a == b || self.is_error_type(a) || self.is_error_type(b),
+        }
+    }
+
+    // Any integer, bool or char converts to any integer with `as`, nothing converts to bool.
+    // Only u8 converts to char, like in Rust, since every byte is a valid code point and wider
+    // integers may not be. Pointers convert to other pointers and to and from 64 bit integers
+    pub fn can_cast(&self, from: &ResolvedType, to: &ResolvedType) -> bool {
+        let is_word = |ty| self.is_pointer(ty) || (self.is_integer(ty) && self.size_of(ty) == 8);
+        self.types_compatible(from, to)
+            || (self.is_integer(to)
+                && (self.is_integer(from) || self.is_bool(from) || self.is_char(from)))
+            || (self.is_char(to)
+                && self.primitive_kind(from)
+                    == Some(PrimitiveKind::Integer {
+                        size: 1,
+                        signed: false,
+                    }))
+            || ((self.is_pointer(from) || self.is_pointer(to)) && is_word(from) && is_word(to))
+    }
+
+    pub fn is_pointer(&self, ty: &ResolvedType) -> bool {
+        matches!(ty, ResolvedType::Pointer(_))
+    }
+
+    pub fn type_name(&self, ty: &ResolvedType) -> String {
+        match ty {
+            ResolvedType::Named(id) => self.symbols[**id].name.to_owned(),
+            ResolvedType::Array(elem, len) => format!("[{}; {len}]", self.type_name(elem)),
+            ResolvedType::Pointer(pointee) => format!("*{}", self.type_name(pointee)),
+            ResolvedType::Str => "str".to_string(),
         }
     }
 
@@ -209,9 +668,10 @@ impl Symbols {
         }
     }
 
-    pub fn increase_stack_size(&mut self, id: SymbolID, size: usize) -> i64 {
+    // Reserves an aligned slot in the function's frame and returns its offset from %rbp
+    pub fn increase_stack_size(&mut self, id: SymbolID, size: usize, align: usize) -> i64 {
         let stack_size = &mut self.func_info_mut(id).stack_size;
-        *stack_size += size;
+        *stack_size = (*stack_size + size).next_multiple_of(align);
         -(*stack_size as i64)
     }
 
@@ -258,14 +718,16 @@ impl Symbols {
     }
 
     fn add_symbol(&mut self, token: &Token, info: SymbolInfo) -> Result<SymbolID, Diagnostic> {
-        if let Some(sym) = self.current_scope().get(&token.lexeme) {
-            return Err(Diagnostic {
-                line: token.line,
-                kind: DiagnosticKind::IdentRedeclared {
-                    original_line: self.symbols[**sym].line,
+        if let Some(sym) = self.current_scope().get(&token.lexeme)
+            && !self.is_poison(*sym)
+        {
+            return Err(Diagnostic::error(
+                DiagnosticKind::IdentRedeclared {
+                    original: self.symbols[**sym].span.map(Box::new),
                     var_name: token.lexeme.to_owned(),
                 },
-            });
+                token.span,
+            ));
         }
 
         let symbol = self.make_symbol_id();
@@ -279,18 +741,20 @@ impl Symbols {
         SymbolID(self.symbols.len())
     }
 
-    fn register_primative(&mut self, name: &str) {
+    fn register_primative(&mut self, name: &str, kind: PrimitiveKind) {
         let symbol = self.make_symbol_id();
         let current_scope = self.current_scope_mut();
 
-        if let Some(_) = current_scope.get(name) {
+        if current_scope.get(name).is_some() {
             panic!("registering duplicate primatives")
         }
 
         current_scope.insert(name.to_owned(), symbol);
         self.symbols.push(SymbolInfo {
-            line: -1,
-            kind: SymbolKind::Type(TypeDefInfo::Primative),
+            name: name.to_owned(),
+            span: None,
+            kind: SymbolKind::Type(TypeDefInfo::Primative(kind)),
+            uses: 0,
         });
     }
 }
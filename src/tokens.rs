@@ -2,13 +2,13 @@ use crate::diagnostic::{Diagnostic, DiagnosticKind};
 use std::fmt::{self};
 
 fn unexpected_token_error(actual: Token, expected: TokenKind) -> Diagnostic {
-    Diagnostic {
-        line: actual.line,
-        kind: DiagnosticKind::UnexpectedToken {
+    Diagnostic::error(
+        DiagnosticKind::UnexpectedToken {
             expected,
             found: actual.kind,
         },
-    }
+        actual.span,
+    )
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -17,16 +17,24 @@ pub enum TokenKind {
     Semi,
     Colon,
     Comma,
+    Dot,
     OpenCurly,
     CloseCurly,
     OpenParen,
     CloseParen,
+    OpenBracket,
+    CloseBracket,
     Bang,
     Eq,
     Plus,
     Minus,
     Star,
     Slash,
+    Percent,
+    Amp,
+    Pipe,
+    Caret,
+    Tilde,
     LessThan,
     GreaterThan,
 
@@ -35,10 +43,26 @@ pub enum TokenKind {
     BangEq,
     EqEq,
     GreaterEq,
+    AmpAmp,
+    PipePipe,
+    LessLess,
+    GreaterGreater,
+    PlusEq,
+    MinusEq,
+    StarEq,
+    SlashEq,
+    PercentEq,
+    AmpEq,
+    PipeEq,
+    CaretEq,
+    LessLessEq,
+    GreaterGreaterEq,
 
     // Dynamic
     Identifier,
     Literal,
+    StrLiteral,  // Lexeme holds the contents, with escape sequences already replaced
+    CharLiteral, // Same as StrLiteral, always a single character
 
     // Keywords
     Return,
@@ -49,6 +73,12 @@ pub enum TokenKind {
     Else,
     While,
     Let,
+    Const,
+    Struct,
+    Str,
+    True,
+    False,
+    As,
 
     // Special
     EOF,
@@ -56,10 +86,22 @@ pub enum TokenKind {
 
 impl TokenKind {
     pub fn starts_expression(&self) -> bool {
-        // match self {
-        //     // TokenKind::Identifier | TokenKind::Literal | TokenKind::OpenParen | TokenKind::Minus | TokenKind::Bang
-        // }
-        true
+        matches!(
+            self,
+            TokenKind::Identifier
+                | TokenKind::Literal
+                | TokenKind::StrLiteral
+                | TokenKind::CharLiteral
+                | TokenKind::OpenParen
+                | TokenKind::OpenBracket
+                | TokenKind::Minus
+                | TokenKind::Bang
+                | TokenKind::Tilde
+                | TokenKind::Amp
+                | TokenKind::Star
+                | TokenKind::True
+                | TokenKind::False
+        )
     }
 }
 
@@ -69,10 +111,13 @@ impl fmt::Display for TokenKind {
             TokenKind::Semi => ";",
             TokenKind::Colon => ":",
             TokenKind::Comma => ",",
+            TokenKind::Dot => ".",
             TokenKind::OpenCurly => "{",
             TokenKind::CloseCurly => "}",
             TokenKind::OpenParen => "(",
             TokenKind::CloseParen => ")",
+            TokenKind::OpenBracket => "[",
+            TokenKind::CloseBracket => "]",
             TokenKind::Bang => "!",
 
             TokenKind::Eq => "=",
@@ -82,13 +127,34 @@ impl fmt::Display for TokenKind {
             TokenKind::Minus => "-",
             TokenKind::Star => "*",
             TokenKind::Slash => "/",
+            TokenKind::Percent => "%",
+            TokenKind::Amp => "&",
+            TokenKind::Pipe => "|",
+            TokenKind::Caret => "^",
+            TokenKind::Tilde => "~",
             TokenKind::LessThan => "<",
             TokenKind::LessEq => "<=",
             TokenKind::GreaterThan => ">",
             TokenKind::GreaterEq => ">=",
+            TokenKind::AmpAmp => "&&",
+            TokenKind::PipePipe => "||",
+            TokenKind::LessLess => "<<",
+            TokenKind::GreaterGreater => ">>",
+            TokenKind::PlusEq => "+=",
+            TokenKind::MinusEq => "-=",
+            TokenKind::StarEq => "*=",
+            TokenKind::SlashEq => "/=",
+            TokenKind::PercentEq => "%=",
+            TokenKind::AmpEq => "&=",
+            TokenKind::PipeEq => "|=",
+            TokenKind::CaretEq => "^=",
+            TokenKind::LessLessEq => "<<=",
+            TokenKind::GreaterGreaterEq => ">>=",
 
             TokenKind::Identifier => "identifier",
             TokenKind::Literal => "literal",
+            TokenKind::StrLiteral => "string literal",
+            TokenKind::CharLiteral => "character literal",
 
             TokenKind::Return => "return",
             TokenKind::Break => "break",
@@ -98,21 +164,55 @@ impl fmt::Display for TokenKind {
             TokenKind::Else => "else",
             TokenKind::While => "while",
             TokenKind::Let => "let",
+            TokenKind::Const => "const",
+            TokenKind::Struct => "struct",
+            TokenKind::Str => "str",
+            TokenKind::True => "true",
+            TokenKind::False => "false",
+            TokenKind::As => "as",
             TokenKind::EOF => "EOF",
         };
         write!(f, "{rep}")
     }
 }
 
-#[derive(Debug, Clone)]
+// Byte range into the source, plus the (1-based) line and column of `low`
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct SourceSpan {
     pub low: usize,
     pub high: usize,
+    pub line: i32,
+    pub col: usize,
 }
 
 impl SourceSpan {
     pub fn dummy() -> Self {
-        SourceSpan { low: 0, high: 0 }
+        SourceSpan {
+            low: 0,
+            high: 0,
+            line: 0,
+            col: 0,
+        }
+    }
+
+    // Part of a span on a single line, between byte offsets relative to its start
+    pub fn slice(self, low: usize, high: usize) -> SourceSpan {
+        SourceSpan {
+            low: self.low + low,
+            high: self.low + high,
+            line: self.line,
+            col: self.col + low,
+        }
+    }
+
+    // Span covering everything from the start of self to the end of other
+    pub fn to(self, other: SourceSpan) -> SourceSpan {
+        SourceSpan {
+            low: self.low,
+            high: other.high.max(self.high),
+            line: self.line,
+            col: self.col,
+        }
     }
 }
 
@@ -124,8 +224,7 @@ impl SourceSpan {
 pub struct Token {
     pub kind: TokenKind,
     pub lexeme: String,
-    // pub span: SourceSpan,
-    pub line: i32,
+    pub span: SourceSpan,
 }
 
 // Nothing to do with proc_macro::TokenStream :)
@@ -150,12 +249,13 @@ impl TokenStream {
         token.clone() // clone is cheap here, plus the TokenStream "serves" tokens, so it should not give ref
     }
 
+    // Unexpected tokens are left in the stream so error recovery can decide what to skip
     pub fn expect(&mut self, expected_kind: TokenKind) -> Result<Token, Diagnostic> {
-        let tok = self.advance();
+        let tok = self.peek();
         if tok.kind != expected_kind {
             return Err(unexpected_token_error(tok, expected_kind));
         }
-        Ok(tok)
+        Ok(self.advance())
     }
 
     pub fn match_kind(&mut self, expected_kind: TokenKind) -> Option<Token> {
@@ -165,6 +265,20 @@ impl TokenStream {
         None
     }
 
+    // Span of the most recently consumed token
+    pub fn prev_span(&self) -> SourceSpan {
+        match self.pos.checked_sub(1) {
+            Some(pos) => self.tokens[pos].span,
+            None => self.peek().span,
+        }
+    }
+
+    // Looks past the next token, `peek_nth(0)` is the same as `peek()`
+    pub fn peek_nth(&self, n: usize) -> Token {
+        let last = self.tokens.len() - 1;
+        self.tokens[(self.pos + n).min(last)].clone()
+    }
+
     pub fn peek(&self) -> Token {
         self.tokens
             .get(self.pos)
@@ -0,0 +1,16 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crescent_lang::diagnostic::Diagnostic;
+use crescent_lang::{BuildMode, Compiler};
+
+pub fn compile(source: &str) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    // Tests run in parallel, so each compilation writes to its own file
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let out_path = std::env::temp_dir().join(format!("crsnt_test_{}_{id}.s", std::process::id()));
+
+    let out = out_path.to_string_lossy().into_owned();
+    let result = Compiler::new(source.to_string(), out, BuildMode::Debug).compile();
+    let _ = std::fs::remove_file(out_path);
+    result
+}
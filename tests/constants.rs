@@ -0,0 +1,93 @@
+// Regression tests for folding the initializers of constants and globals
+
+mod common;
+
+use common::compile;
+use crescent_lang::diagnostic::{Diagnostic, DiagnosticKind};
+
+fn with_main(decls: &str) -> String {
+    format!("{decls}\nfunc main() : i64 {{ return 0; }}")
+}
+
+fn is_const_overflow(diags: &[Diagnostic]) -> bool {
+    diags
+        .iter()
+        .any(|diag| matches!(diag.kind, DiagnosticKind::ConstOverflow { .. }))
+}
+
+#[test]
+fn overflowing_constants_are_rejected() {
+    for decl in [
+        "const M: u8 = 255 + 1;",
+        "const D: i64 = (-9223372036854775807 - 1) / -1;",
+        "const R: i64 = (-9223372036854775807 - 1) % -1;",
+        "const U: u64 = 0 - 1;",
+        "let G: i32 = 2147483647 * 2;",
+    ] {
+        let diags = compile(&with_main(decl)).unwrap_err();
+        assert!(is_const_overflow(&diags), "{decl}");
+    }
+}
+
+#[test]
+fn casts_and_in_range_results_still_fold() {
+    let decls = "const W: u8 = 300 as u8;
+                 const S: i8 = -128 % -1;
+                 const U: u64 = 18446744073709551615 / 3 + 1;
+                 let G: i8 = -127 - 1;";
+    assert!(compile(&with_main(decls)).is_ok());
+}
+
+#[test]
+fn mistyped_global_initializers_are_reported_not_folded() {
+    for decls in [
+        "struct S { a: [i64; 2] } let g: S = S { a: [1, 2, 3] };",
+        "let g: [i64; 2] = [1, 2, 3];",
+    ] {
+        let diags = compile(&with_main(decls)).unwrap_err();
+        assert!(
+            diags
+                .iter()
+                .any(|diag| matches!(diag.kind, DiagnosticKind::TypeMismatch { .. })),
+            "{decls}"
+        );
+    }
+}
+
+#[test]
+fn constant_pointer_arithmetic_moves_by_elements() {
+    // The folded offset shows up through the compile-time bounds check on constant indices
+    let source = "const P: *i64 = 0 as *i64 + 1;
+                  const Q: *i32 = 16 as *i32 - 2;
+                  func main() : i64 {
+                      let a: [i64; 4] = [0; 4];
+                      return a[P as i64] + a[Q as i64];
+                  }";
+    let diags = compile(source).unwrap_err();
+    let indices: Vec<i64> = diags
+        .iter()
+        .filter_map(|diag| match diag.kind {
+            DiagnosticKind::IndexOutOfBounds { index, .. } => Some(index),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(indices, [8, 8]);
+}
+
+#[test]
+fn globals_can_hold_strings() {
+    let decls = r#"struct Named { id: i64, name: str }
+                   let g: str = "hello";
+                   let names: [str; 2] = ["a", ""];
+                   let n: Named = Named { name: "x", id: 1 };"#;
+    assert!(compile(&with_main(decls)).is_ok());
+}
+
+#[test]
+fn mistyped_constants_are_reported_not_folded() {
+    let diags =
+        compile("const C: u8 = 60000u16 + 60000u16;\nfunc main() : i64 { return C as i64; }")
+            .unwrap_err();
+    assert_eq!(diags.len(), 1, "{diags:?}");
+    assert!(matches!(diags[0].kind, DiagnosticKind::TypeMismatch { .. }));
+}
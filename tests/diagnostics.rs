@@ -0,0 +1,64 @@
+// Regression tests for which diagnostics a program gets, and how many
+
+mod common;
+
+use common::compile;
+use crescent_lang::diagnostic::{Diagnostic, DiagnosticKind};
+
+fn unused_vars(diags: &[Diagnostic]) -> Vec<&str> {
+    diags
+        .iter()
+        .filter_map(|diag| match &diag.kind {
+            DiagnosticKind::UnusedVar { var_name } => Some(var_name.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn writes_do_not_count_as_uses() {
+    let source = "func main() : i64 {
+                      let x: i64 = 0;
+                      x = 5;
+                      let y: i64 = 0;
+                      y += 1;
+                      let z: i64 = 0;
+                      z = 1;
+                      return z;
+                  }";
+    let diags = compile(source).unwrap();
+    assert_eq!(unused_vars(&diags), ["x", "y"]);
+}
+
+#[test]
+fn void_operands_are_reported_once() {
+    for body in [
+        "return f() + 1;",
+        "return 1 + f();",
+        "let v: i64 = f() * 2; return v;",
+    ] {
+        let diags = compile(&format!("func f() {{}}\nfunc main() : i64 {{ {body} }}")).unwrap_err();
+        assert_eq!(diags.len(), 1, "{body}: {diags:?}");
+        assert!(matches!(diags[0].kind, DiagnosticKind::VoidValue), "{body}");
+    }
+}
+
+#[test]
+fn string_bytes_have_no_address() {
+    for body in [
+        r#"let p: *u8 = &"abc"[0]; return *p as i64;"#,
+        r#"let s: str = "abc"; let p: *u8 = &s[0]; *p = 1u8; return 0;"#,
+    ] {
+        let diags = compile(&format!("func main() : i64 {{ {body} }}")).unwrap_err();
+        assert!(
+            diags
+                .iter()
+                .any(|diag| matches!(diag.kind, DiagnosticKind::StrImmutable)),
+            "{body}"
+        );
+    }
+    assert!(
+        compile(r#"func main() : i64 { let s: str = "abc"; let p: *str = &s; return (*p).len; }"#)
+            .is_ok()
+    );
+}
@@ -0,0 +1,193 @@
+// Regression tests for how `-` is lexed and parsed, with and without spaces around it, for how
+// prefix operators and comparisons group, and for which casts are allowed
+
+mod common;
+
+use common::compile;
+use crescent_lang::BuildMode;
+use crescent_lang::ast::{Expr, ExprKind, StmtKind};
+use crescent_lang::compiler::Context;
+use crescent_lang::diagnostic::{Diagnostic, DiagnosticKind};
+use crescent_lang::lexer::Lexer;
+use crescent_lang::parser::Parser;
+use crescent_lang::tokens::TokenKind;
+
+fn token_kinds(source: &str) -> Vec<TokenKind> {
+    let ctx = Context::new(source.to_string(), String::new(), BuildMode::Debug);
+    let mut stream = Lexer::new(&ctx).tokenize();
+
+    let mut kinds = vec![];
+    loop {
+        let token = stream.advance();
+        if token.kind == TokenKind::EOF {
+            return kinds;
+        }
+        kinds.push(token.kind);
+    }
+}
+
+// Parses `expr` as the value returned from main and renders it fully parenthesized
+fn shape(expr: &str) -> String {
+    let source = format!("func main() : i64 {{ return {expr}; }}");
+    let ctx = Context::new(source, String::new(), BuildMode::Debug);
+    let stream = Lexer::new(&ctx).tokenize();
+    let program = Parser::new(stream, &ctx).parse();
+    assert!(!ctx.diags.borrow().has_errors(), "`{expr}` failed to parse");
+
+    let StmtKind::FuncDecl(func) = &program.top[0].kind else {
+        panic!("expected main");
+    };
+    let StmtKind::Block(body) = &func.body.kind else {
+        panic!("expected a body");
+    };
+    let StmtKind::Return(ret) = &body[0].kind else {
+        panic!("expected a return");
+    };
+    render(ret.expr.as_ref().unwrap())
+}
+
+fn render(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::Literal(info) => info.value.to_string(),
+        ExprKind::Var(_) => expr.token.lexeme.to_owned(),
+        ExprKind::UnOp(info) => format!("({} {})", expr.token.lexeme, render(&info.expr)),
+        ExprKind::BinOp(info) => format!(
+            "({} {} {})",
+            expr.token.lexeme,
+            render(&info.lhs),
+            render(&info.rhs)
+        ),
+        kind => panic!("unexpected expression {kind:?}"),
+    }
+}
+
+fn compile_main(body: &str) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+    compile(&format!("func main() : i64 {{ {body} }}"))
+}
+
+fn is_chained_comparison(diags: &[Diagnostic]) -> bool {
+    diags
+        .iter()
+        .any(|diag| matches!(diag.kind, DiagnosticKind::ChainedComparison { .. }))
+}
+
+fn is_out_of_range(diags: &[Diagnostic]) -> bool {
+    diags
+        .iter()
+        .any(|diag| matches!(diag.kind, DiagnosticKind::IntLiteralOutOfRange { .. }))
+}
+
+#[test]
+fn minus_is_always_an_operator() {
+    use TokenKind::*;
+    for source in ["a-1", "a - 1", "a -1", "a- 1"] {
+        assert_eq!(
+            token_kinds(source),
+            [Identifier, Minus, Literal],
+            "{source}"
+        );
+    }
+    assert_eq!(token_kinds("-1"), [Minus, Literal]);
+    assert_eq!(token_kinds("a-=1"), [Identifier, MinusEq, Literal]);
+    assert_eq!(token_kinds("a--1"), [Identifier, Minus, Minus, Literal]);
+}
+
+#[test]
+fn subtraction_without_spaces() {
+    assert_eq!(shape("n-1"), "(- n 1)");
+    assert_eq!(shape("n -1"), "(- n 1)");
+    assert_eq!(shape("n-1-2"), "(- (- n 1) 2)");
+    assert_eq!(shape("f-g*2"), "(- f (* g 2))");
+}
+
+#[test]
+fn negative_literals_are_folded() {
+    assert_eq!(shape("-1"), "-1");
+    assert_eq!(shape("- 1"), "(- 1)");
+    assert_eq!(shape("-(1)"), "(- 1)");
+    assert_eq!(shape("--1"), "1");
+    assert_eq!(shape("n - -1"), "(- n -1)");
+    assert_eq!(shape("n--1"), "(- n -1)");
+}
+
+#[test]
+fn unary_minus_binds_tighter_than_binary_operators() {
+    assert_eq!(shape("n*-1+2"), "(+ (* n -1) 2)");
+    assert_eq!(shape("-1 + 2"), "(+ -1 2)");
+    assert_eq!(shape("-n + 2"), "(+ (- n) 2)");
+    assert_eq!(shape("n - -2 != 12"), "(!= (- n -2) 12)");
+}
+
+#[test]
+fn prefix_operators_bind_tighter_than_binary_operators() {
+    assert_eq!(shape("-a + b"), "(+ (- a) b)");
+    assert_eq!(shape("!x == y"), "(== (! x) y)");
+    assert_eq!(shape("~a & b"), "(& (~ a) b)");
+    assert_eq!(shape("-a * b"), "(* (- a) b)");
+    assert_eq!(shape("*p + 1"), "(+ (* p) 1)");
+    assert_eq!(shape("a * *p"), "(* a (* p))");
+    assert_eq!(shape("!!x || y"), "(|| (! (! x)) y)");
+    assert_eq!(shape("-~a"), "(- (~ a))");
+}
+
+#[test]
+fn comparisons_do_not_chain() {
+    assert_eq!(shape("a < b == c"), "(== (< a b) c)");
+    assert_eq!(shape("a == b && b == c"), "(&& (== a b) (== b c))");
+
+    let diags = compile_main("let a: i64 = 1; return (a < 2 < 3) as i64;").unwrap_err();
+    assert!(is_chained_comparison(&diags));
+    let diags = compile_main("let a: bool = true; return (a == a == a) as i64;").unwrap_err();
+    assert!(is_chained_comparison(&diags));
+    let diags = compile_main("let a: i64 = 1; return (a != 2 == true) as i64;").unwrap_err();
+    assert!(is_chained_comparison(&diags));
+}
+
+#[test]
+fn i64_min_literal() {
+    assert!(compile_main("let m: i64 = -9223372036854775808; return m + 1;").is_ok());
+    assert!(compile_main("return -9223372036854775808 + 1;").is_ok());
+
+    let diags = compile_main("let m: i64 = 9223372036854775808; return m;").unwrap_err();
+    assert!(is_out_of_range(&diags));
+    let diags = compile_main("let m: i64 = -9223372036854775809; return m;").unwrap_err();
+    assert!(is_out_of_range(&diags));
+
+    let diags = compile_main("return -(9223372036854775808);").unwrap_err();
+    let messages: Vec<_> = diags.iter().map(|diag| diag.kind.to_string()).collect();
+    assert_eq!(messages, ["Literal 9223372036854775808 does not fit in type 'i64'"]);
+}
+
+#[test]
+fn negative_literals_are_range_checked_per_type() {
+    assert!(compile_main("let a: i8 = -128; let b: i8 = -128i8; return (a - b) as i64;").is_ok());
+
+    let diags = compile_main("let a: i8 = -129; return a as i64;").unwrap_err();
+    assert!(is_out_of_range(&diags));
+    let diags = compile_main("let a: u8 = -1u8; return a as i64;").unwrap_err();
+    assert!(is_out_of_range(&diags));
+}
+
+#[test]
+fn spacing_does_not_change_the_program() {
+    for expr in ["n-1", "n - 1", "n -1", "n- 1"] {
+        let result = compile_main(&format!("let n: i64 = 5; return {expr};"));
+        assert!(result.is_ok(), "{expr}");
+    }
+}
+
+#[test]
+fn only_u8_casts_to_char() {
+    assert!(compile_main("let c: char = 65u8 as char; return c as i64;").is_ok());
+
+    for expr in ["-1", "0xD800", "0x110000", "65", "65u32"] {
+        let diags =
+            compile_main(&format!("let c: char = {expr} as char; return c as i64;")).unwrap_err();
+        assert!(
+            diags
+                .iter()
+                .any(|diag| matches!(diag.kind, DiagnosticKind::InvalidCharCast { .. })),
+            "{expr}"
+        );
+    }
+}